@@ -0,0 +1,34 @@
+use std::path::Path;
+use std::process::Command;
+
+/// Records the commit the solver is built from in `GIT_COMMIT`, so that `bench` can note it
+/// without needing git or the checkout when it runs. Nothing is set outside a git checkout.
+fn main() {
+    println!("cargo:rerun-if-changed=src");
+    println!("cargo:rerun-if-changed=tests");
+    println!("cargo:rerun-if-changed=Cargo.toml");
+    if let Some(git_dir) = git(&["rev-parse", "--git-dir"]) {
+        let git_dir = Path::new(&git_dir);
+        for name in ["HEAD", "index"] {
+            println!("cargo:rerun-if-changed={}", git_dir.join(name).display());
+        }
+        if let Some(head_ref) = git(&["symbolic-ref", "-q", "HEAD"]) {
+            println!(
+                "cargo:rerun-if-changed={}",
+                git_dir.join(head_ref).display()
+            );
+        }
+    }
+    if let Some(commit) = git(&["describe", "--always", "--dirty"]) {
+        println!("cargo:rustc-env=GIT_COMMIT={commit}");
+    }
+}
+
+fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git")
+        .args(args)
+        .output()
+        .ok()
+        .filter(|x| x.status.success())?;
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
@@ -0,0 +1,249 @@
+use crate::progress::Progress;
+use crate::run::{flush_sinks, open_sinks, watch_progress, Settings, Sinks, STOP};
+use crate::solve_puzzle::{solve_puzzle, solve_puzzle_counted, SearchState};
+use crate::solver_data::{prepare_pieces_and_heuristics, SolverData};
+use crate::structs::SolverResult;
+use crate::util::derive_seed;
+use chrono::Utc;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Instant;
+use thousands::Separable;
+
+/// The commit the solver was built from, as `git describe --always --dirty` gave it at build
+/// time, or `unknown` when it was not built in a git checkout.
+pub const BUILD_COMMIT: &str = match option_env!("GIT_COMMIT") {
+    Some(commit) => commit,
+    None => "unknown",
+};
+
+/// One run of `bench`, kept so that later runs with the same settings can be compared with it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BenchRecord {
+    /// When the run finished, in RFC 3339 format.
+    pub timestamp: String,
+    /// The commit the solver was built from, from `BUILD_COMMIT`.
+    pub commit: Option<String>,
+    /// The name the run's settings would be recorded under, which `bench` does not write. Runs
+    /// with the same profile searched the same boards, unless the search itself has changed.
+    pub profile: Option<String>,
+    pub threads: usize,
+    pub seed: u64,
+    pub max_node_count: u64,
+    pub nodes: u64,
+    pub seconds: f64,
+    pub candidates_scanned: u64,
+    pub backtracks: u64,
+    /// The deepest board any thread reached.
+    pub max_depth: usize,
+}
+
+impl BenchRecord {
+    pub fn nodes_per_second(&self) -> f64 {
+        self.nodes as f64 / self.seconds
+    }
+
+    pub fn candidates_per_node(&self) -> f64 {
+        self.candidates_scanned as f64 / self.nodes.max(1) as f64
+    }
+
+    /// The share of nodes at which the search took a piece back.
+    pub fn backtrack_ratio(&self) -> f64 {
+        self.backtracks as f64 / self.nodes.max(1) as f64
+    }
+
+    /// Whether `other` visited the same boards in the same order, as far as the counts show.
+    pub fn same_search(&self, other: &BenchRecord) -> bool {
+        (
+            self.nodes,
+            self.candidates_scanned,
+            self.backtracks,
+            self.max_depth,
+        ) == (
+            other.nodes,
+            other.candidates_scanned,
+            other.backtracks,
+            other.max_depth,
+        )
+    }
+}
+
+/// Reads every run recorded in a JSON lines file, oldest first. A missing file holds none.
+pub fn load_bench_records(path: &Path) -> io::Result<Vec<BenchRecord>> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e),
+    };
+    text.lines()
+        .filter(|x| !x.trim().is_empty())
+        .map(|x| serde_json::from_str(x).map_err(io::Error::from))
+        .collect()
+}
+
+/// Adds a run to the end of a JSON lines file.
+pub fn append_bench_record(path: &Path, record: &BenchRecord) -> io::Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", serde_json::to_string(record)?)
+}
+
+/// Runs a fixed number of searches from a fixed seed and reports how fast they went, then runs
+/// them again counting the candidates scanned and the backtracks, which would slow the timed run.
+/// The run is added to `results`, after comparing it with the last one there with the same
+/// settings.
+pub fn run_bench(results: Option<PathBuf>, settings: Settings) -> Result<(), String> {
+    let threads = settings.threads;
+    let master_seed = settings.seed;
+    let max_node_count = settings.max_node_count;
+    let solver_data = Arc::new(prepare_pieces_and_heuristics(
+        &settings.puzzle,
+        &settings.config,
+        derive_seed(master_seed, &[1]),
+    ));
+    let sinks = open_sinks(&settings)?;
+    let progress = Arc::new(Progress::new(threads, settings.puzzle.num_cells()));
+    watch_progress(&progress, &settings)?;
+
+    info!(
+        "Benchmarking {} nodes on {threads} threads with seed {master_seed}...",
+        max_node_count.separate_with_commas()
+    );
+    let stopwatch = Instant::now();
+    let timed = bench_searches(&solver_data, &settings, Some(&sinks), Some(&progress));
+    let elapsed_seconds = stopwatch.elapsed().as_secs_f64();
+    flush_sinks(&sinks);
+
+    let mut total_nodes: u64 = 0;
+    for (thread, solver_result) in timed.iter().enumerate() {
+        info!(
+            "Thread {thread:02}: {} nodes, best depth {}",
+            solver_result.nodes.separate_with_commas(),
+            solver_result.max_depth
+        );
+        total_nodes += solver_result.nodes;
+    }
+    info!(
+        "Total {} nodes in {elapsed_seconds:.2} seconds, {} per second",
+        total_nodes.separate_with_commas(),
+        ((total_nodes as f64 / elapsed_seconds) as u64).separate_with_commas()
+    );
+    if STOP.load(Ordering::Relaxed) {
+        warn!("Stopped early, so the run is not recorded");
+        return Ok(());
+    }
+
+    info!("Running the same searches again to count candidates and backtracks...");
+    let counted = bench_searches(&solver_data, &settings, None, None);
+    if STOP.load(Ordering::Relaxed) {
+        warn!("Stopped early, so the run is not recorded");
+        return Ok(());
+    }
+    let record = BenchRecord {
+        timestamp: Utc::now().to_rfc3339(),
+        commit: Some(BUILD_COMMIT.to_string()),
+        profile: settings.config.profile.clone(),
+        threads,
+        seed: master_seed,
+        max_node_count,
+        nodes: total_nodes,
+        seconds: elapsed_seconds,
+        candidates_scanned: counted.iter().map(|x| x.candidates_scanned).sum(),
+        backtracks: counted.iter().map(|x| x.backtracks).sum(),
+        max_depth: timed.iter().map(|x| x.max_depth).max().unwrap_or(0),
+    };
+    if counted.iter().map(|x| x.nodes).sum::<u64>() != total_nodes {
+        error!("The counted searches visited a different number of nodes from the timed ones");
+    }
+    info!(
+        "{:.3} candidates scanned per node, {:.2}% of nodes backtracked",
+        record.candidates_per_node(),
+        100.0 * record.backtrack_ratio()
+    );
+
+    let results = results.unwrap_or_else(|| settings.config.output_dir.join("bench.jsonl"));
+    let earlier = load_bench_records(&results).unwrap_or_else(|e| {
+        warn!(
+            "Failed to read earlier runs from {}: {e}",
+            results.display()
+        );
+        vec![]
+    });
+    match earlier
+        .iter()
+        .rev()
+        .find(|x| x.profile.is_some() && x.profile == record.profile)
+    {
+        Some(last) => {
+            info!(
+                "Last run with these settings, at {} on {}: {} per second, this run {:+.1}%",
+                last.commit.as_deref().unwrap_or("an unknown commit"),
+                last.timestamp,
+                (last.nodes_per_second() as u64).separate_with_commas(),
+                100.0 * (record.nodes_per_second() / last.nodes_per_second() - 1.0)
+            );
+            if !record.same_search(last) {
+                warn!(
+                    "The search has changed since then, when it scanned {:.3} candidates per node, {:.2}% of nodes backtracked and the best depth was {}",
+                    last.candidates_per_node(),
+                    100.0 * last.backtrack_ratio(),
+                    last.max_depth
+                );
+            }
+        }
+        None => info!("No earlier run with these settings to compare with"),
+    }
+    match append_bench_record(&results, &record) {
+        Ok(()) => info!("Run recorded in {}", results.display()),
+        Err(e) => error!("Failed to record the run in {}: {e}", results.display()),
+    }
+    Ok(())
+}
+
+/// Runs one search per thread from the bench seeds, to the node budget or until stopped.
+/// Searches without a sink are counted.
+fn bench_searches(
+    solver_data: &Arc<SolverData>,
+    settings: &Settings,
+    sinks: Option<&Sinks>,
+    progress: Option<&Arc<Progress>>,
+) -> Vec<SolverResult> {
+    let handles: Vec<_> = (0..settings.threads)
+        .map(|thread| {
+            let solver_data = Arc::clone(solver_data);
+            let sinks = sinks.map(|x| Arc::clone(&x.all));
+            let progress = progress.map(Arc::clone);
+            let seed = derive_seed(settings.seed, &[1, thread as u64, 1]);
+            let max_node_count = settings.max_node_count;
+            std::thread::spawn(move || {
+                let state = SearchState::new(&solver_data, thread, seed);
+                let worker_progress = progress.as_ref().map(|x| x.worker(thread));
+                match &sinks {
+                    Some(sinks) => solve_puzzle(
+                        &solver_data,
+                        state,
+                        max_node_count,
+                        Some(sinks),
+                        worker_progress.as_deref(),
+                        Some(&STOP),
+                        None,
+                    ),
+                    None => solve_puzzle_counted(
+                        &solver_data,
+                        state,
+                        max_node_count,
+                        None,
+                        worker_progress.as_deref(),
+                        Some(&STOP),
+                        None,
+                    ),
+                }
+            })
+        })
+        .collect();
+    handles.into_iter().map(|x| x.join().unwrap()).collect()
+}
@@ -0,0 +1,452 @@
+use crate::puzzle::Puzzle;
+use crate::structs::{BoardRecord, RotatedPiece};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// A board read back from a file written by `save_board`. Cells are indexed like the solver's
+/// board, bottom row first, and carry only the piece ID and rotations.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BoardFile {
+    pub width: usize,
+    pub height: usize,
+    pub board: Vec<RotatedPiece>,
+    /// The depth, breaks and matched edges the file records for the board. Only the JSON formats
+    /// record them.
+    pub depth: Option<usize>,
+    pub breaks: Option<usize>,
+    pub matched_edges: Option<usize>,
+    pub url: Option<String>,
+    pub solver_data_seed: Option<u64>,
+    pub search_seed: Option<u64>,
+    pub profile: Option<String>,
+}
+
+pub fn load_board(path: &Path) -> io::Result<BoardFile> {
+    parse_board(&fs::read_to_string(path)?)
+}
+
+pub fn load_boards(path: &Path) -> io::Result<Vec<BoardFile>> {
+    parse_boards(&fs::read_to_string(path)?)
+}
+
+/// Parses a file holding a single board, in any of the output formats.
+pub fn parse_board(text: &str) -> io::Result<BoardFile> {
+    let mut boards = parse_boards(text)?;
+    if boards.len() != 1 {
+        return Err(invalid_data(format!(
+            "expected one board, found {}",
+            boards.len()
+        )));
+    }
+    Ok(boards.remove(0))
+}
+
+/// Parses every board in a file: a text board, a JSON board, or a `boards.jsonl` file with one
+/// JSON board per line. JSON is told apart from text by its opening brace.
+pub fn parse_boards(text: &str) -> io::Result<Vec<BoardFile>> {
+    if !text.trim_start().starts_with('{') {
+        return Ok(vec![parse_text_board(text)?]);
+    }
+    // A JSON board is written over several lines, so only try lines one at a time when the whole
+    // text is not one board.
+    if let Ok(record) = serde_json::from_str::<BoardRecord>(text) {
+        return Ok(vec![board_from_record(record)?]);
+    }
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(line_number, line)| {
+            let error =
+                |message: String| invalid_data(format!("line {}: {message}", line_number + 1));
+            let record = serde_json::from_str(line).map_err(|e| error(e.to_string()))?;
+            board_from_record(record).map_err(|e| error(e.to_string()))
+        })
+        .collect()
+}
+
+fn board_from_record(record: BoardRecord) -> io::Result<BoardFile> {
+    let (width, height) = (record.width, record.height);
+    let mut board = vec![RotatedPiece::default(); width * height];
+    for cell in &record.cells {
+        if cell.row >= height || cell.column >= width {
+            return Err(invalid_data(format!(
+                "cell at row {}, column {} is outside the {width}x{height} board",
+                cell.row, cell.column
+            )));
+        }
+        let index = cell.row * width + cell.column;
+        if cell.reid == 0 || board[index].reid > 0 {
+            return Err(invalid_data(format!(
+                "invalid cell at row {}, column {}",
+                cell.row, cell.column
+            )));
+        }
+        board[index] = RotatedPiece {
+            reid: cell.reid,
+            rotations: cell.rotations,
+            ..RotatedPiece::default()
+        };
+    }
+    Ok(BoardFile {
+        width,
+        height,
+        board,
+        depth: Some(record.depth),
+        breaks: Some(record.breaks),
+        matched_edges: Some(record.matched_edges),
+        url: Some(record.url),
+        solver_data_seed: Some(record.solver_data_seed),
+        search_seed: Some(record.search_seed),
+        profile: record.profile,
+    })
+}
+
+/// Parses the text `save_board` writes: a grid of `reid/rotations` cells, or `---/-` for empty
+/// ones, with the top row first, then the e2.bucas.name link, the seeds and the profile.
+fn parse_text_board(text: &str) -> io::Result<BoardFile> {
+    let mut rows: Vec<Vec<RotatedPiece>> = Vec::new();
+    let mut url = None;
+    let mut solver_data_seed = None;
+    let mut search_seed = None;
+    let mut profile = None;
+
+    for (line_number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        let error = |message: String| invalid_data(format!("line {}: {message}", line_number + 1));
+
+        if line.is_empty() {
+            continue;
+        } else if line.starts_with("https://") {
+            url = Some(line.to_string());
+        } else if let Some(value) = line.strip_prefix("Solver data seed:") {
+            solver_data_seed = Some(value.trim().parse().map_err(|e| error(format!("{e}")))?);
+        } else if let Some(value) = line.strip_prefix("Search seed:") {
+            search_seed = Some(value.trim().parse().map_err(|e| error(format!("{e}")))?);
+        } else if let Some(value) = line.strip_prefix("Profile:") {
+            profile = Some(value.trim().to_string());
+        } else if url.is_none() {
+            let row = line
+                .split_whitespace()
+                .map(parse_cell)
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(error)?;
+            if rows.first().is_some_and(|x| x.len() != row.len()) {
+                return Err(error(format!(
+                    "expected {} cells, found {}",
+                    rows[0].len(),
+                    row.len()
+                )));
+            }
+            rows.push(row);
+        } else {
+            return Err(error(format!("unexpected line {line:?}")));
+        }
+    }
+
+    if rows.is_empty() {
+        return Err(invalid_data("no board found".to_string()));
+    }
+    let width = rows[0].len();
+    let height = rows.len();
+    Ok(BoardFile {
+        width,
+        height,
+        board: rows.into_iter().rev().flatten().collect(),
+        depth: None,
+        breaks: None,
+        matched_edges: None,
+        url,
+        solver_data_seed,
+        search_seed,
+        profile,
+    })
+}
+
+/// A board rebuilt from an e2.bucas.name link.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkedBoard {
+    pub board_file: BoardFile,
+    /// Cells, as (row, column) from the bottom left, whose edges match no piece in the set or
+    /// only pieces already placed elsewhere. They are left empty.
+    pub unidentified: Vec<(usize, usize)>,
+}
+
+/// Rebuilds a board from an e2.bucas.name link, or from just its `board_edges` value, by finding
+/// the piece and rotation whose sides match each cell's four edges. The link's `board_w` and
+/// `board_h` are used when present; otherwise the board is taken to be the puzzle's size.
+pub fn parse_board_link(puzzle: &Puzzle, link: &str) -> Result<LinkedBoard, String> {
+    let link = link.trim();
+    let param = |name: &str| {
+        link.split(['#', '?', '&'])
+            .find_map(|x| x.strip_prefix(name)?.strip_prefix('='))
+    };
+    let edges = if link.contains('=') {
+        param("board_edges").ok_or("link has no board_edges")?
+    } else {
+        link
+    };
+    let dimension = |name: &str, default: usize| match param(name) {
+        Some(value) => value
+            .parse::<usize>()
+            .map_err(|e| format!("invalid {name} {value:?}: {e}")),
+        None => Ok(default),
+    };
+    let width = dimension("board_w", puzzle.width)?;
+    let height = dimension("board_h", puzzle.height)?;
+
+    let colours = edges
+        .bytes()
+        .map(|x| match x {
+            b'a'..=b'z' => Ok(x - b'a'),
+            _ => Err(format!("invalid edge {:?} in board_edges", x as char)),
+        })
+        .collect::<Result<Vec<u8>, _>>()?;
+    if colours.len() != width * height * 4 {
+        return Err(format!(
+            "board_edges has {} edges, expected {} for a {width}x{height} board",
+            colours.len(),
+            width * height * 4
+        ));
+    }
+
+    // Every way of placing every piece, by the sides it shows.
+    let mut placements: BTreeMap<[u8; 4], Vec<RotatedPiece>> = BTreeMap::new();
+    for piece in &puzzle.pieces {
+        for rotations in 0..4 {
+            let p = piece.rotated(rotations);
+            let candidates = placements
+                .entry([p.top, p.right, p.bottom, p.left])
+                .or_default();
+            if candidates.iter().all(|x| x.reid != p.reid) {
+                candidates.push(RotatedPiece {
+                    reid: p.reid,
+                    rotations,
+                    ..RotatedPiece::default()
+                });
+            }
+        }
+    }
+
+    let mut board = vec![RotatedPiece::default(); width * height];
+    let mut used = vec![false; puzzle.pieces.len() + 1];
+    let mut unidentified = Vec::new();
+    for (i, sides) in colours.chunks(4).enumerate() {
+        let row = height - 1 - i / width;
+        let col = i % width;
+        if sides == [0, 0, 0, 0] {
+            continue;
+        }
+        let found = placements
+            .get(sides)
+            .and_then(|x| x.iter().find(|x| !used[x.reid as usize]));
+        match found {
+            Some(&rotated_piece) => {
+                used[rotated_piece.reid as usize] = true;
+                board[row * width + col] = rotated_piece;
+            }
+            None => unidentified.push((row, col)),
+        }
+    }
+    unidentified.sort();
+
+    Ok(LinkedBoard {
+        board_file: BoardFile {
+            width,
+            height,
+            board,
+            depth: None,
+            breaks: None,
+            matched_edges: None,
+            url: None,
+            solver_data_seed: None,
+            search_seed: None,
+            profile: None,
+        },
+        unidentified,
+    })
+}
+
+fn parse_cell(cell: &str) -> Result<RotatedPiece, String> {
+    if cell == "---/-" {
+        return Ok(RotatedPiece::default());
+    }
+    let parsed = cell
+        .split_once('/')
+        .and_then(|(reid, rotations)| Some((reid.parse().ok()?, rotations.parse().ok()?)));
+    match parsed {
+        Some((reid, rotations)) if reid > 0 => Ok(RotatedPiece {
+            reid,
+            rotations,
+            ..RotatedPiece::default()
+        }),
+        _ => Err(format!("invalid cell {cell:?}")),
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structs::{CellRecord, Piece};
+
+    fn record(depth: usize) -> BoardRecord {
+        BoardRecord {
+            depth,
+            width: 2,
+            height: 1,
+            cells: vec![CellRecord {
+                row: 0,
+                column: 1,
+                reid: 7,
+                rotations: 3,
+            }],
+            breaks: 0,
+            matched_edges: 0,
+            timestamp: "2024-01-01T00:00:00+00:00".to_string(),
+            solver_data_seed: 1,
+            search_seed: 2,
+            profile: None,
+            worker: 0,
+            url: "https://e2.bucas.name/".to_string(),
+        }
+    }
+
+    #[test]
+    fn text_boards_read_back_top_row_first() {
+        let text = "---/-   7/3\n  1/0   2/1\nhttps://e2.bucas.name/\n\nSearch seed: 5\n";
+        let board_file = parse_board(text).unwrap();
+        assert_eq!((board_file.width, board_file.height), (2, 2));
+        let cells: Vec<_> = board_file
+            .board
+            .iter()
+            .map(|x| (x.reid, x.rotations))
+            .collect();
+        assert_eq!(cells, [(1, 0), (2, 1), (0, 0), (7, 3)]);
+        assert_eq!((board_file.search_seed, board_file.depth), (Some(5), None));
+
+        assert!(parse_board("1/0 2/0\n3/0\n").is_err());
+        assert!(parse_board("1/0 0/0\n").is_err());
+    }
+
+    #[test]
+    fn json_boards_read_back_with_their_depth() {
+        let json = serde_json::to_string_pretty(&record(1)).unwrap();
+        let board_file = parse_board(&json).unwrap();
+        assert_eq!(board_file.depth, Some(1));
+        assert_eq!(
+            (board_file.board[1].reid, board_file.board[1].rotations),
+            (7, 3)
+        );
+        assert_eq!(board_file.board[0].reid, 0);
+        assert_eq!(board_file.solver_data_seed, Some(1));
+
+        let mut outside = record(1);
+        outside.cells[0].column = 2;
+        assert!(parse_board(&serde_json::to_string(&outside).unwrap()).is_err());
+    }
+
+    #[test]
+    fn json_lines_hold_a_board_per_line() {
+        let lines = [record(1), record(2)]
+            .iter()
+            .map(|x| serde_json::to_string(x).unwrap() + "\n")
+            .collect::<String>();
+        let depths: Vec<_> = parse_boards(&lines)
+            .unwrap()
+            .iter()
+            .map(|x| x.depth)
+            .collect();
+        assert_eq!(depths, [Some(1), Some(2)]);
+        assert!(parse_board(&lines).is_err());
+
+        let error = parse_boards(&(lines + "{\n")).unwrap_err();
+        assert!(error.to_string().starts_with("line 3:"), "{error}");
+    }
+
+    fn corner_puzzle() -> Puzzle {
+        let piece = |reid, top, right, bottom, left| Piece {
+            reid,
+            top,
+            right,
+            bottom,
+            left,
+        };
+        Puzzle::from_pieces(vec![
+            piece(1, 1, 2, 0, 0),
+            piece(2, 1, 0, 0, 2),
+            piece(3, 0, 3, 1, 0),
+            piece(4, 0, 0, 1, 3),
+        ])
+        .unwrap()
+    }
+
+    fn cells(board_file: &BoardFile) -> Vec<(u16, u8)> {
+        board_file
+            .board
+            .iter()
+            .map(|x| (x.reid, x.rotations))
+            .collect()
+    }
+
+    #[test]
+    fn links_rebuild_the_board() {
+        let puzzle = corner_puzzle();
+        // Top row first, each cell's top, right, bottom and left edges.
+        let link = "https://e2.bucas.name/#puzzle=x&board_w=2&board_h=2&board_edges=adba\
+                    aabdbcaabaac&motifs_order=jblackwood";
+        let linked_board = parse_board_link(&puzzle, link).unwrap();
+        assert!(linked_board.unidentified.is_empty());
+        assert_eq!(
+            cells(&linked_board.board_file),
+            [(1, 0), (2, 0), (3, 0), (4, 0)]
+        );
+
+        // A bare board_edges value, on a board the size of the puzzle.
+        let bare = parse_board_link(&puzzle, "adbaaabdbcaabaac").unwrap();
+        assert_eq!(bare, linked_board);
+    }
+
+    #[test]
+    fn each_piece_is_matched_once() {
+        let puzzle = corner_puzzle();
+        // The top right cell repeats piece 3, which is already used, so it is left empty, as is
+        // the grey cell.
+        let linked_board = parse_board_link(&puzzle, "adbaadbabcaaaaaa").unwrap();
+        assert_eq!(linked_board.unidentified, [(1, 1)]);
+        assert_eq!(
+            cells(&linked_board.board_file),
+            [(1, 0), (0, 0), (3, 0), (0, 0)]
+        );
+
+        // Pieces are found wherever they sit and however they are turned.
+        let moved = parse_board_link(&puzzle, "abcaaabdbaacadba").unwrap();
+        assert!(moved.unidentified.is_empty());
+        let board = &moved.board_file.board;
+        let reids: Vec<_> = board.iter().map(|x| x.reid).collect();
+        assert_eq!(reids, [2, 3, 1, 4]);
+        let turned = puzzle.pieces[0].rotated(board[2].rotations);
+        assert_eq!(
+            [turned.top, turned.right, turned.bottom, turned.left],
+            [0, 1, 2, 0]
+        );
+    }
+
+    #[test]
+    fn links_must_describe_the_whole_board() {
+        let puzzle = corner_puzzle();
+        assert!(parse_board_link(&puzzle, "https://e2.bucas.name/#board_w=2").is_err());
+        assert!(parse_board_link(&puzzle, "adbaaabd").is_err());
+        assert!(parse_board_link(&puzzle, "adbaaabdbcaabaaC").is_err());
+        assert!(parse_board_link(&puzzle, "board_w=x&board_edges=adbaaabdbcaabaac").is_err());
+
+        // The link's own size wins over the puzzle's.
+        let wide = parse_board_link(&puzzle, "board_w=4&board_h=1&board_edges=adbaaabdbcaabaac");
+        let board_file = wide.unwrap().board_file;
+        assert_eq!((board_file.width, board_file.height), (4, 1));
+    }
+}
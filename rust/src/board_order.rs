@@ -49,17 +49,114 @@ const BOARD_ORDER_RAW: [[u8; 16]; 16] = [
     [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
 ];
 
-pub fn get_board_order() -> [SearchIndex; 256] {
-    let mut board_search_sequence = [SearchIndex { row: 0, column: 0 }; 256];
-    for row in 0..16 {
-        #[allow(clippy::needless_range_loop)]
-        for col in 0..16 {
-            let piece_seq_num = BOARD_ORDER_RAW[15 - row][col] as usize;
+/// Returns the position in the search order of each cell, top row first. The 16x16 board uses
+/// the hand-tuned order above; other sizes are filled row by row from the bottom left.
+pub fn default_board_order(width: usize, height: usize) -> Vec<Vec<usize>> {
+    if width == 16 && height == 16 {
+        return BOARD_ORDER_RAW
+            .iter()
+            .map(|row| row.iter().map(|&x| x as usize).collect())
+            .collect();
+    }
+    (0..height)
+        .rev()
+        .map(|row| (0..width).map(|col| row * width + col).collect())
+        .collect()
+}
+
+/// Checks that an order fits the board and that every cell comes after the cells below and to
+/// the left of it, which the search relies on to find its candidates. The bottom left corner must
+/// come first.
+pub fn check_board_order(order: &[Vec<usize>], width: usize, height: usize) -> Result<(), String> {
+    if order.len() != height || order.iter().any(|row| row.len() != width) {
+        return Err(format!(
+            "board order must have {height} rows of {width} cells"
+        ));
+    }
+    let position = |row: usize, col: usize| order[height - 1 - row][col];
+
+    let mut seen = vec![false; width * height];
+    for row in 0..height {
+        for col in 0..width {
+            let x = position(row, col);
+            if x >= width * height || seen[x] {
+                return Err(format!(
+                    "board order position {x} is out of range or repeated"
+                ));
+            }
+            seen[x] = true;
+            if (row > 0 && position(row - 1, col) > x) || (col > 0 && position(row, col - 1) > x) {
+                return Err(format!(
+                    "board order fills row {row}, column {col} before the cell below or to its left"
+                ));
+            }
+        }
+    }
+    if position(0, 0) != 0 {
+        return Err("board order must start in the bottom left corner".to_string());
+    }
+    Ok(())
+}
+
+/// Turns an order from `default_board_order` or `check_board_order` into the cell to fill at
+/// each position.
+pub fn get_board_order(order: &[Vec<usize>]) -> Vec<SearchIndex> {
+    let height = order.len();
+    let num_cells = order.iter().map(|row| row.len()).sum();
+    let mut board_search_sequence = vec![SearchIndex { row: 0, column: 0 }; num_cells];
+    for (i, cells) in order.iter().enumerate() {
+        for (col, &piece_seq_num) in cells.iter().enumerate() {
             board_search_sequence[piece_seq_num] = SearchIndex {
-                row: row as u8,
+                row: (height - 1 - i) as u8,
                 column: col as u8,
             };
         }
     }
     board_search_sequence
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_orders_are_valid() {
+        for (width, height) in [(16, 16), (3, 3), (5, 4), (7, 3)] {
+            let order = default_board_order(width, height);
+            assert_eq!(check_board_order(&order, width, height), Ok(()));
+        }
+    }
+
+    #[test]
+    fn small_boards_fill_row_by_row_from_the_bottom_left() {
+        let sequence = get_board_order(&default_board_order(4, 3));
+        let cells: Vec<_> = sequence.iter().map(|x| (x.row, x.column)).collect();
+        let expected: Vec<_> = (0..3)
+            .flat_map(|row| (0..4).map(move |col| (row, col)))
+            .collect();
+        assert_eq!(cells, expected);
+    }
+
+    #[test]
+    fn board_order_visits_every_cell_at_its_position() {
+        let order = default_board_order(16, 16);
+        let sequence = get_board_order(&order);
+        for (position, cell) in sequence.iter().enumerate() {
+            assert_eq!(
+                order[15 - cell.row as usize][cell.column as usize],
+                position
+            );
+        }
+    }
+
+    #[test]
+    fn bad_orders_are_rejected() {
+        // Filling a column before the row is fine.
+        assert!(check_board_order(&[vec![1, 4, 5], vec![0, 2, 3]], 3, 2).is_ok());
+        // Wrong size, a repeated position, a cell before the one below it, and a late start.
+        assert!(check_board_order(&[vec![0, 1], vec![2, 3]], 3, 2).is_err());
+        assert!(check_board_order(&[vec![3, 3, 5], vec![0, 1, 2]], 3, 2).is_err());
+        assert!(check_board_order(&[vec![0, 4, 5], vec![1, 2, 3]], 3, 2).is_err());
+        assert!(check_board_order(&[vec![3, 4, 5], vec![1, 0, 2]], 3, 2).is_err());
+    }
+}
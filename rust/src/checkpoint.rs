@@ -0,0 +1,345 @@
+use crate::solve_puzzle::SearchState;
+use crate::solver_data::SolverData;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Totals carried over from loops that have already finished.
+#[derive(Serialize, Deserialize)]
+pub struct RunCheckpoint {
+    pub loop_count: u64,
+    pub index_counts: Vec<u64>,
+    pub max_depth: usize,
+}
+
+/// Written with a borrowed `SolverData` and read back with an owned one.
+#[derive(Serialize, Deserialize)]
+struct SolverDataCheckpoint<S> {
+    loop_count: u64,
+    solver_data: S,
+}
+
+/// A loop that has been started but not finished: how many searches it runs per repeat, the
+/// ones that have finished, and their totals.
+#[derive(Serialize, Deserialize)]
+pub struct LoopCheckpoint {
+    pub loop_count: u64,
+    pub slots: usize,
+    /// The `(slot, repeat)` of each finished search.
+    pub finished: Vec<(usize, u32)>,
+    pub index_counts: Vec<u64>,
+    pub max_depth: usize,
+}
+
+/// The state of a search in progress.
+#[derive(Serialize, Deserialize)]
+pub struct JobCheckpoint {
+    pub loop_count: u64,
+    pub slot: usize,
+    pub repeat: u32,
+    pub state: SearchState,
+}
+
+/// How far an exhaustive run has got through its prefixes, and the totals of the searches
+/// below them.
+#[derive(Serialize, Deserialize)]
+pub struct CoverageCheckpoint {
+    pub prefix_depth: usize,
+    /// The hash of the prefixes, from `prefixes_hash`, to check that a resumed run lists the
+    /// same ones.
+    pub prefixes_hash: String,
+    pub num_prefixes: usize,
+    /// The node budget that the cut off prefixes ran out of.
+    pub max_node_count: u64,
+    /// The index of each prefix searched to the end.
+    pub finished: Vec<usize>,
+    /// The index of each prefix whose search ran out of nodes.
+    pub cut_off: Vec<usize>,
+    pub index_counts: Vec<u64>,
+    /// The part of `index_counts` counted below the cut off prefixes, which is taken out again
+    /// when they are searched with a larger budget.
+    #[serde(default)]
+    pub cut_off_counts: Vec<u64>,
+    pub max_depth: usize,
+}
+
+/// Saves and restores a run's state in a directory: one file for the totals of the finished
+/// loops, and for each loop in progress its solver data, its finished searches and the state
+/// of each search that is under way. An exhaustive run keeps its coverage in a file of its own.
+pub struct Checkpoint {
+    dir: PathBuf,
+}
+
+impl Checkpoint {
+    pub fn new(dir: &Path) -> io::Result<Checkpoint> {
+        fs::create_dir_all(dir)?;
+        Ok(Checkpoint {
+            dir: dir.to_path_buf(),
+        })
+    }
+
+    pub fn load_run(&self) -> io::Result<Option<RunCheckpoint>> {
+        read_json(&self.dir.join("run.json"))
+    }
+
+    pub fn save_run(&self, run: &RunCheckpoint) -> io::Result<()> {
+        write_json(&self.dir.join("run.json"), run)
+    }
+
+    pub fn load_coverage(&self) -> io::Result<Option<CoverageCheckpoint>> {
+        read_json(&self.dir.join("coverage.json"))
+    }
+
+    pub fn save_coverage(&self, coverage: &CoverageCheckpoint) -> io::Result<()> {
+        write_json(&self.dir.join("coverage.json"), coverage)
+    }
+
+    /// Returns the loops in progress, in order.
+    pub fn loops(&self) -> io::Result<Vec<LoopCheckpoint>> {
+        let mut loops = vec![];
+        for entry in fs::read_dir(&self.dir)? {
+            let name = entry?.file_name();
+            let name = name.to_string_lossy();
+            if name.starts_with("loop_") && name.ends_with(".json") {
+                loops.extend(read_json::<LoopCheckpoint>(&self.dir.join(&*name))?);
+            }
+        }
+        loops.sort_by_key(|x| x.loop_count);
+        Ok(loops)
+    }
+
+    pub fn save_loop(&self, checkpoint: &LoopCheckpoint) -> io::Result<()> {
+        write_json(&self.loop_path(checkpoint.loop_count), checkpoint)
+    }
+
+    pub fn load_solver_data(&self, loop_count: u64) -> io::Result<Option<SolverData>> {
+        let checkpoint: Option<SolverDataCheckpoint<SolverData>> =
+            read_json(&self.solver_data_path(loop_count))?;
+        Ok(checkpoint.map(|x| x.solver_data))
+    }
+
+    pub fn save_solver_data(&self, loop_count: u64, solver_data: &SolverData) -> io::Result<()> {
+        let checkpoint = SolverDataCheckpoint {
+            loop_count,
+            solver_data,
+        };
+        write_json(&self.solver_data_path(loop_count), &checkpoint)
+    }
+
+    pub fn load_job(
+        &self,
+        loop_count: u64,
+        slot: usize,
+        repeat: u32,
+    ) -> io::Result<Option<JobCheckpoint>> {
+        read_json(&self.job_path(loop_count, slot, repeat))
+    }
+
+    pub fn save_job(&self, job: &JobCheckpoint) -> io::Result<()> {
+        write_json(&self.job_path(job.loop_count, job.slot, job.repeat), job)
+    }
+
+    /// Removes the state of a search once it has finished and its loop has been saved.
+    pub fn remove_job(&self, loop_count: u64, slot: usize, repeat: u32) -> io::Result<()> {
+        remove_file(&self.job_path(loop_count, slot, repeat))
+    }
+
+    /// Removes a loop's files once its totals have been added to the run.
+    pub fn remove_loop(&self, loop_count: u64) -> io::Result<()> {
+        remove_file(&self.solver_data_path(loop_count))?;
+        remove_file(&self.loop_path(loop_count))
+    }
+
+    fn loop_path(&self, loop_count: u64) -> PathBuf {
+        self.dir.join(format!("loop_{loop_count}.json"))
+    }
+
+    fn solver_data_path(&self, loop_count: u64) -> PathBuf {
+        self.dir.join(format!("solver_data_{loop_count}.json"))
+    }
+
+    fn job_path(&self, loop_count: u64, slot: usize, repeat: u32) -> PathBuf {
+        self.dir
+            .join(format!("job_{loop_count}_{slot:02}_{repeat}.json"))
+    }
+}
+
+fn remove_file(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+fn read_json<T: DeserializeOwned>(path: &Path) -> io::Result<Option<T>> {
+    match fs::read_to_string(path) {
+        Ok(text) => Ok(Some(serde_json::from_str(&text)?)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Writes to a temporary file first so that a crash mid-write never leaves a torn checkpoint.
+pub(crate) fn write_json<T: Serialize>(path: &Path, value: &T) -> io::Result<()> {
+    let temp_path = path.with_extension("json.tmp");
+    fs::write(&temp_path, serde_json::to_vec(value)?)?;
+    fs::rename(temp_path, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SolverConfig;
+    use crate::generate::generate_puzzle;
+    use crate::solve_puzzle::solve_puzzle;
+    use crate::solver_data::prepare_pieces_and_heuristics;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Mutex;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "eternity-solver-checkpoint-{name}-{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn loop_checkpoint(loop_count: u64) -> LoopCheckpoint {
+        LoopCheckpoint {
+            loop_count,
+            slots: 2,
+            finished: vec![(1, 0)],
+            index_counts: vec![1, 2, 3],
+            max_depth: 3,
+        }
+    }
+
+    #[test]
+    fn totals_read_back_as_written() {
+        let dir = temp_dir("totals");
+        let checkpoint = Checkpoint::new(&dir).unwrap();
+        assert!(checkpoint.load_run().unwrap().is_none());
+        assert!(checkpoint.load_coverage().unwrap().is_none());
+
+        checkpoint
+            .save_run(&RunCheckpoint {
+                loop_count: 4,
+                index_counts: vec![5, 6],
+                max_depth: 2,
+            })
+            .unwrap();
+        let run = checkpoint.load_run().unwrap().unwrap();
+        assert_eq!(
+            (run.loop_count, run.index_counts, run.max_depth),
+            (4, vec![5, 6], 2)
+        );
+
+        checkpoint
+            .save_coverage(&CoverageCheckpoint {
+                prefix_depth: 3,
+                prefixes_hash: "abc".to_string(),
+                num_prefixes: 10,
+                max_node_count: 100,
+                finished: vec![0, 2],
+                cut_off: vec![1],
+                index_counts: vec![7],
+                cut_off_counts: vec![2],
+                max_depth: 5,
+            })
+            .unwrap();
+        let coverage = checkpoint.load_coverage().unwrap().unwrap();
+        assert_eq!(
+            (coverage.prefixes_hash, coverage.finished, coverage.cut_off),
+            ("abc".to_string(), vec![0, 2], vec![1])
+        );
+
+        // Loops come back in order, not in the order the directory lists them.
+        for loop_count in [10, 9, 2] {
+            checkpoint.save_loop(&loop_checkpoint(loop_count)).unwrap();
+        }
+        let loops: Vec<_> = checkpoint
+            .loops()
+            .unwrap()
+            .iter()
+            .map(|x| x.loop_count)
+            .collect();
+        assert_eq!(loops, [2, 9, 10]);
+        checkpoint.remove_loop(9).unwrap();
+        let loops: Vec<_> = checkpoint
+            .loops()
+            .unwrap()
+            .iter()
+            .map(|x| x.loop_count)
+            .collect();
+        assert_eq!(loops, [2, 10]);
+
+        // No temporary files are left behind.
+        assert!(fs::read_dir(&dir).unwrap().all(|x| !x
+            .unwrap()
+            .file_name()
+            .to_string_lossy()
+            .ends_with(".tmp")));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn searches_resume_from_their_files() {
+        let dir = temp_dir("resume");
+        let puzzle = generate_puzzle(5, 5, 3, 5, 11).unwrap().puzzle;
+        let config = SolverConfig::for_board(puzzle.width, puzzle.height);
+        let solver_data = prepare_pieces_and_heuristics(&puzzle, &config, 11);
+        let straight = solve_puzzle(
+            &solver_data,
+            SearchState::new(&solver_data, 0, 5),
+            50_000,
+            None,
+            None,
+            None,
+            None,
+        );
+
+        // A search stopped at once hands over its state, which is saved with its solver data.
+        let checkpoint = Checkpoint::new(&dir).unwrap();
+        checkpoint.save_solver_data(3, &solver_data).unwrap();
+        checkpoint.save_loop(&loop_checkpoint(3)).unwrap();
+        let saved = Mutex::new(None);
+        let save = |state: SearchState| *saved.lock().unwrap() = Some(state);
+        let stopped = solve_puzzle(
+            &solver_data,
+            SearchState::new(&solver_data, 0, 5),
+            50_000,
+            None,
+            None,
+            Some(&AtomicBool::new(true)),
+            Some(&save),
+        );
+        assert!(stopped.stopped);
+        let state = saved.into_inner().unwrap().unwrap();
+        checkpoint
+            .save_job(&JobCheckpoint {
+                loop_count: 3,
+                slot: 1,
+                repeat: 0,
+                state,
+            })
+            .unwrap();
+
+        // A new run finds the loop, its solver data and the search, and carries on as before.
+        let checkpoint = Checkpoint::new(&dir).unwrap();
+        let loop_count = checkpoint.loops().unwrap()[0].loop_count;
+        let solver_data = checkpoint.load_solver_data(loop_count).unwrap().unwrap();
+        let job = checkpoint.load_job(loop_count, 1, 0).unwrap().unwrap();
+        assert!(checkpoint.load_job(loop_count, 0, 0).unwrap().is_none());
+        let resumed = solve_puzzle(&solver_data, job.state, 50_000, None, None, None, None);
+        assert_eq!(resumed.solve_indexes, straight.solve_indexes);
+        assert_eq!(resumed.best_board, straight.best_board);
+
+        checkpoint.remove_job(loop_count, 1, 0).unwrap();
+        checkpoint.remove_loop(loop_count).unwrap();
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,574 @@
+use clap::{Args, Parser, Subcommand};
+use eternityii_solver::board_order::check_board_order;
+use eternityii_solver::config::{HeuristicSegment, OutputFormat, SolverConfig};
+use eternityii_solver::piece_file::load_pieces;
+use eternityii_solver::pieces;
+use eternityii_solver::puzzle::Puzzle;
+use eternityii_solver::run::Settings;
+use eternityii_solver::sinks::SinkSpec;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{self, Path, PathBuf};
+
+/// Eternity II solver. Runs `solve` when no command is given.
+#[derive(Parser)]
+#[command(version, args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[command(flatten)]
+    pub settings: SettingsArgs,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Search for solutions until stopped, saving deep boards to the output directory.
+    Solve(SettingsArgs),
+    /// Measure search speed with a fixed seed and node budget, and compare it with earlier runs.
+    Bench(BenchArgs),
+    /// Search every board below each way of making the first placements, recording which are
+    /// finished.
+    Exhaust(ExhaustArgs),
+    /// Estimate how many boards the search can reach at each depth from random probes.
+    Estimate(EstimateArgs),
+    /// Count every solution of a small puzzle, optionally saving each distinct one.
+    Count(CountArgs),
+    /// Check saved board files against the piece set.
+    Verify(VerifyArgs),
+    /// Rebuild a board from an e2.bucas.name link and write it as a board file.
+    Import(ImportArgs),
+    /// Draw a saved board as an SVG or PNG image.
+    Render(RenderArgs),
+    /// Build a random solvable puzzle and write it as a piece file with its solution.
+    Generate(GenerateArgs),
+    /// Join a `solve --listen` coordinator and run the searches it hands out.
+    Work(WorkArgs),
+}
+
+#[derive(Args)]
+pub struct WorkArgs {
+    /// Address of the coordinator, as given to its `--listen`.
+    pub coordinator: String,
+
+    /// Worker threads [default: one fewer than the number of cores, at least 1].
+    #[arg(long)]
+    pub threads: Option<usize>,
+
+    /// Name the coordinator logs this worker under [default: the host name and process ID].
+    #[arg(long)]
+    pub name: Option<String>,
+
+    /// Seconds between progress reports of this worker's own threads, or 0 for none.
+    #[arg(long, default_value_t = 0)]
+    pub progress_interval: u64,
+}
+
+#[derive(Args)]
+pub struct BenchArgs {
+    /// JSON lines file that each run is added to and compared against
+    /// [default: bench.jsonl in the output directory].
+    #[arg(long)]
+    pub results: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub settings: SettingsArgs,
+}
+
+#[derive(Args)]
+pub struct ExhaustArgs {
+    /// How many placements each prefix fixes, from the first corner on in the search order.
+    #[arg(long, default_value_t = 4)]
+    pub prefix_depth: usize,
+
+    #[command(flatten)]
+    pub settings: SettingsArgs,
+}
+
+#[derive(Args)]
+pub struct EstimateArgs {
+    /// Random paths from the empty board to average over, shared between the threads.
+    #[arg(long, default_value_t = 100_000)]
+    pub probes: u64,
+
+    #[command(flatten)]
+    pub settings: SettingsArgs,
+}
+
+#[derive(Args)]
+pub struct CountArgs {
+    /// How many placements each prefix fixes, which sets how finely the work is shared between
+    /// the threads.
+    #[arg(long, default_value_t = 2)]
+    pub prefix_depth: usize,
+
+    /// Send each distinct solution to the sinks, turned to the orientation that sorts first.
+    #[arg(long)]
+    pub write: bool,
+
+    #[command(flatten)]
+    pub settings: SettingsArgs,
+}
+
+#[derive(Args)]
+pub struct VerifyArgs {
+    /// Piece file the boards were solved with [default: built-in set].
+    #[arg(long)]
+    pub pieces: Option<PathBuf>,
+
+    /// Board files written by `solve`, in any output format. Every board in a `boards.jsonl`
+    /// file is checked.
+    #[arg(required = true)]
+    pub files: Vec<PathBuf>,
+}
+
+impl VerifyArgs {
+    pub fn puzzle(&self) -> Result<Puzzle, String> {
+        load_puzzle(self.pieces.as_deref())
+    }
+}
+
+#[derive(Args)]
+pub struct ImportArgs {
+    /// Piece file the board uses [default: built-in set].
+    #[arg(long)]
+    pub pieces: Option<PathBuf>,
+
+    /// File to write the board to, in the format `solve` saves boards in [default: print it].
+    #[arg(long)]
+    pub output: Option<PathBuf>,
+
+    /// An e2.bucas.name link, or just its `board_edges` value.
+    pub link: String,
+}
+
+#[derive(Args)]
+pub struct GenerateArgs {
+    /// Columns on the board. The longer side is laid out across, as for any piece file.
+    #[arg(long)]
+    pub width: usize,
+
+    /// Rows on the board.
+    #[arg(long)]
+    pub height: usize,
+
+    /// Colours on the edges between border pieces.
+    #[arg(long, default_value_t = 5)]
+    pub border_colours: u8,
+
+    /// Colours on every other inner edge. With the border colours there can be at most 25, so that
+    /// board links can show each as a letter.
+    #[arg(long, default_value_t = 17)]
+    pub interior_colours: u8,
+
+    /// Seed from which the puzzle is built [default: random].
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Piece file to write [default: print it].
+    #[arg(long)]
+    pub output: Option<PathBuf>,
+
+    /// File to write the planted solution to, in the format `solve` saves boards in.
+    #[arg(long)]
+    pub solution: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct RenderArgs {
+    /// Piece file the board uses [default: built-in set].
+    #[arg(long)]
+    pub pieces: Option<PathBuf>,
+
+    /// Image to write, as PNG if its name ends in `.png` and SVG otherwise [default: the board
+    /// file with an `.svg` extension].
+    #[arg(long)]
+    pub output: Option<PathBuf>,
+
+    /// Width and height of each cell in pixels.
+    #[arg(long, default_value_t = 48)]
+    pub cell_size: u32,
+
+    /// Write each piece's ID on it.
+    #[arg(long)]
+    pub ids: bool,
+
+    /// Write each piece's rotations on it.
+    #[arg(long)]
+    pub rotations: bool,
+
+    /// Leave unmatched edges unmarked.
+    #[arg(long)]
+    pub no_breaks: bool,
+
+    /// Board file written by `solve` or `import`. Each board in a `boards.jsonl` file is drawn to
+    /// its own image, numbered from 1.
+    pub file: PathBuf,
+}
+
+impl RenderArgs {
+    pub fn puzzle(&self) -> Result<Puzzle, String> {
+        load_puzzle(self.pieces.as_deref())
+    }
+}
+
+impl ImportArgs {
+    pub fn puzzle(&self) -> Result<Puzzle, String> {
+        load_puzzle(self.pieces.as_deref())
+    }
+}
+
+/// Settings shared by every command. Each flag overrides the same key in the config file.
+#[derive(Args, Default)]
+pub struct SettingsArgs {
+    /// TOML file with default values for any of the settings below, using the same names with
+    /// underscores, e.g. `max_node_count = 1000000`, and named profiles under `[profiles.<name>]`.
+    /// The file can also set `board_order` and `heuristic_curve`.
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+
+    /// Profile from the config file whose settings override the file's top-level ones.
+    #[arg(long, requires = "config")]
+    pub profile: Option<String>,
+
+    /// Piece file with one `top right bottom left` line per piece [default: built-in set].
+    #[arg(long)]
+    pub pieces: Option<PathBuf>,
+
+    /// Worker threads [default: one fewer than the number of cores, at least 1;
+    /// 1 for bench].
+    #[arg(long)]
+    pub threads: Option<usize>,
+
+    /// Searches in each repeat of a loop [default: the number of threads].
+    #[arg(long)]
+    pub slots: Option<usize>,
+
+    /// Address, such as `0.0.0.0:7878`, at which to accept `work` processes, which then take
+    /// searches alongside the local threads [default: none].
+    #[arg(long)]
+    pub listen: Option<String>,
+
+    /// Master seed from which every random choice is derived [default: random; 0 for bench,
+    /// exhaust, estimate and count].
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Directory in which to checkpoint and resume the search.
+    #[arg(long)]
+    pub checkpoint: Option<PathBuf>,
+
+    /// Directory that deep boards and the run's settings are saved to
+    /// [default: ~/EternitySolutions].
+    #[arg(long)]
+    pub output_dir: Option<PathBuf>,
+
+    /// How saved boards are written: `text`, `json` for one JSON file per board, or `jsonl` to
+    /// append a line per board to `boards.jsonl` [default: text].
+    #[arg(long)]
+    pub output_format: Option<OutputFormat>,
+
+    /// Where deep boards are sent, given once per sink: `directory` for the output directory,
+    /// `stdout`, `jsonl=<path>` to append every find to a JSON Lines file, or `webhook=<url>` to
+    /// post each one to an `http://` endpoint [default: directory].
+    #[arg(long = "sink")]
+    pub sinks: Option<Vec<SinkSpec>>,
+
+    /// Nodes each search visits before it restarts [default: 500,000,000; 100,000,000 for bench;
+    /// no limit for exhaust and count].
+    #[arg(long)]
+    pub max_node_count: Option<u64>,
+
+    /// Seconds between progress reports while searching, or 0 for none [default: 60; 0 for bench].
+    #[arg(long)]
+    pub progress_interval: Option<u64>,
+
+    /// Address, such as `127.0.0.1:9100`, at which to serve Prometheus metrics at `/metrics`
+    /// [default: none].
+    #[arg(long)]
+    pub metrics_addr: Option<String>,
+
+    /// Boards at least this deep are saved [default: 252, or the board size if smaller].
+    #[arg(long)]
+    pub min_solve_index_to_save: Option<usize>,
+
+    /// Comma-separated colours whose placement the heuristic rewards [default: 13,16,10].
+    #[arg(long, value_delimiter = ',')]
+    pub heuristic_sides: Option<Vec<u8>>,
+
+    /// Comma-separated positions in the search order at which another break is allowed
+    /// [default: the tuned positions on 16x16, none otherwise].
+    #[arg(long, value_delimiter = ',')]
+    pub break_indexes_allowed: Option<Vec<usize>>,
+
+    /// Last position in the search order at which the heuristic is enforced [default: 160 on
+    /// 16x16, 0 otherwise].
+    #[arg(long)]
+    pub max_heuristic_index: Option<usize>,
+}
+
+/// The settings as they appear in a config file or profile, and in the record of a run.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct SettingsFile {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pieces: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    threads: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    slots: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    listen: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    checkpoint: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output_dir: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output_format: Option<OutputFormat>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sinks: Option<Vec<SinkSpec>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_node_count: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    progress_interval: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metrics_addr: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    min_solve_index_to_save: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    heuristic_sides: Option<Vec<u8>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    break_indexes_allowed: Option<Vec<usize>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_heuristic_index: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    board_order: Option<Vec<Vec<usize>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    heuristic_curve: Option<Vec<HeuristicSegment>>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    profiles: BTreeMap<String, SettingsFile>,
+}
+
+impl SettingsFile {
+    /// Takes each setting from `self`, falling back to `other`.
+    fn or(self, other: SettingsFile) -> SettingsFile {
+        SettingsFile {
+            pieces: self.pieces.or(other.pieces),
+            threads: self.threads.or(other.threads),
+            slots: self.slots.or(other.slots),
+            listen: self.listen.or(other.listen),
+            seed: self.seed.or(other.seed),
+            checkpoint: self.checkpoint.or(other.checkpoint),
+            output_dir: self.output_dir.or(other.output_dir),
+            output_format: self.output_format.or(other.output_format),
+            sinks: self.sinks.or(other.sinks),
+            max_node_count: self.max_node_count.or(other.max_node_count),
+            progress_interval: self.progress_interval.or(other.progress_interval),
+            metrics_addr: self.metrics_addr.or(other.metrics_addr),
+            min_solve_index_to_save: self
+                .min_solve_index_to_save
+                .or(other.min_solve_index_to_save),
+            heuristic_sides: self.heuristic_sides.or(other.heuristic_sides),
+            break_indexes_allowed: self.break_indexes_allowed.or(other.break_indexes_allowed),
+            max_heuristic_index: self.max_heuristic_index.or(other.max_heuristic_index),
+            board_order: self.board_order.or(other.board_order),
+            heuristic_curve: self.heuristic_curve.or(other.heuristic_curve),
+            profiles: BTreeMap::new(),
+        }
+    }
+}
+
+/// Defaults for the settings that depend on the command.
+pub struct RunDefaults {
+    pub threads: usize,
+    pub seed: u64,
+    pub max_node_count: u64,
+    pub progress_interval: u64,
+}
+
+impl SettingsArgs {
+    pub fn resolve(self, defaults: RunDefaults) -> Result<Settings, String> {
+        let mut file = match &self.config {
+            Some(path) => read_settings_file(path)?,
+            None => SettingsFile::default(),
+        };
+        let profile = match &self.profile {
+            Some(name) => file.profiles.remove(name).ok_or_else(|| {
+                let names: Vec<_> = file.profiles.keys().map(String::as_str).collect();
+                format!(
+                    "No profile named {name} in the config file, which has: {}",
+                    names.join(", ")
+                )
+            })?,
+            None => SettingsFile::default(),
+        };
+        let description = match (&self.config, &self.profile) {
+            (Some(path), Some(name)) => format!("profile {name} from {}", path.display()),
+            (Some(path), None) => path.display().to_string(),
+            _ => "the command line".to_string(),
+        };
+        let settings = self.overrides().or(profile).or(file);
+
+        let puzzle = load_puzzle(settings.pieces.as_deref())?;
+
+        let defaults_for_board = SolverConfig::for_board(puzzle.width, puzzle.height);
+        let config = SolverConfig {
+            min_solve_index_to_save: settings
+                .min_solve_index_to_save
+                .unwrap_or(defaults_for_board.min_solve_index_to_save),
+            output_dir: settings
+                .output_dir
+                .map_or(Ok(defaults_for_board.output_dir), absolute)?,
+            output_format: settings
+                .output_format
+                .unwrap_or(defaults_for_board.output_format),
+            profile: None,
+            board_order: settings
+                .board_order
+                .unwrap_or(defaults_for_board.board_order),
+            heuristic_sides: settings
+                .heuristic_sides
+                .unwrap_or(defaults_for_board.heuristic_sides),
+            break_indexes_allowed: settings
+                .break_indexes_allowed
+                .unwrap_or(defaults_for_board.break_indexes_allowed),
+            max_heuristic_index: settings
+                .max_heuristic_index
+                .unwrap_or(defaults_for_board.max_heuristic_index),
+            heuristic_curve: settings
+                .heuristic_curve
+                .unwrap_or(defaults_for_board.heuristic_curve),
+        };
+        check_board_order(&config.board_order, puzzle.width, puzzle.height)?;
+        let sinks = settings
+            .sinks
+            .unwrap_or(vec![SinkSpec::Directory])
+            .into_iter()
+            .map(|x| match x {
+                SinkSpec::Jsonl(path) => absolute(path).map(SinkSpec::Jsonl),
+                x => Ok(x),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let threads = settings.threads.unwrap_or(defaults.threads);
+        let resolved = SettingsFile {
+            pieces: settings.pieces.map(absolute).transpose()?,
+            threads: Some(threads),
+            slots: Some(settings.slots.unwrap_or(threads)),
+            listen: settings.listen,
+            seed: Some(settings.seed.unwrap_or(defaults.seed)),
+            checkpoint: settings.checkpoint.map(absolute).transpose()?,
+            output_dir: Some(config.output_dir.clone()),
+            output_format: Some(config.output_format),
+            sinks: Some(sinks.clone()),
+            max_node_count: Some(settings.max_node_count.unwrap_or(defaults.max_node_count)),
+            progress_interval: Some(
+                settings
+                    .progress_interval
+                    .unwrap_or(defaults.progress_interval),
+            ),
+            metrics_addr: settings.metrics_addr,
+            min_solve_index_to_save: Some(config.min_solve_index_to_save),
+            heuristic_sides: Some(config.heuristic_sides.clone()),
+            break_indexes_allowed: Some(config.break_indexes_allowed.clone()),
+            max_heuristic_index: Some(config.max_heuristic_index),
+            board_order: Some(config.board_order.clone()),
+            heuristic_curve: Some(config.heuristic_curve.clone()),
+            profiles: BTreeMap::new(),
+        };
+        let record = format!(
+            "# Settings resolved from {description}. Without `pieces` the built-in set is used.\n{}",
+            toml::to_string(&resolved).map_err(|e| format!("Failed to record settings: {e}"))?
+        );
+
+        Ok(Settings {
+            puzzle,
+            config,
+            threads: resolved.threads.unwrap(),
+            slots: resolved.slots.unwrap(),
+            listen: resolved.listen,
+            seed: resolved.seed.unwrap(),
+            checkpoint: resolved.checkpoint,
+            max_node_count: resolved.max_node_count.unwrap(),
+            progress_interval: resolved.progress_interval.unwrap(),
+            metrics_addr: resolved.metrics_addr,
+            sinks,
+            record,
+        })
+    }
+
+    fn overrides(self) -> SettingsFile {
+        SettingsFile {
+            pieces: self.pieces,
+            threads: self.threads,
+            slots: self.slots,
+            listen: self.listen,
+            seed: self.seed,
+            checkpoint: self.checkpoint,
+            output_dir: self.output_dir,
+            output_format: self.output_format,
+            sinks: self.sinks,
+            max_node_count: self.max_node_count,
+            progress_interval: self.progress_interval,
+            metrics_addr: self.metrics_addr,
+            min_solve_index_to_save: self.min_solve_index_to_save,
+            heuristic_sides: self.heuristic_sides,
+            break_indexes_allowed: self.break_indexes_allowed,
+            max_heuristic_index: self.max_heuristic_index,
+            board_order: None,
+            heuristic_curve: None,
+            profiles: BTreeMap::new(),
+        }
+    }
+}
+
+/// Builds the puzzle from a piece file, or from the built-in set when there is none.
+fn load_puzzle(pieces: Option<&Path>) -> Result<Puzzle, String> {
+    let board_pieces = match pieces {
+        Some(path) => load_pieces(path)
+            .map_err(|e| format!("Failed to load pieces from {}: {e}", path.display()))?,
+        None => pieces::PIECES.to_vec(),
+    };
+    Puzzle::from_pieces(board_pieces)
+}
+
+/// Reads a settings file. Relative paths in it, including those in its profiles, are taken from
+/// the file's own directory.
+fn read_settings_file(path: &Path) -> Result<SettingsFile, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read config file {}: {e}", path.display()))?;
+    let mut file: SettingsFile = toml::from_str(&text)
+        .map_err(|e| format!("Failed to parse config file {}: {e}", path.display()))?;
+
+    let base = path.parent().unwrap_or(Path::new(""));
+    join_paths(&mut file, base);
+    for profile in file.profiles.values_mut() {
+        join_paths(profile, base);
+    }
+    if file.profiles.values().any(|x| !x.profiles.is_empty()) {
+        return Err(format!(
+            "Profiles in config file {} cannot contain profiles",
+            path.display()
+        ));
+    }
+    Ok(file)
+}
+
+fn join_paths(settings: &mut SettingsFile, base: &Path) {
+    settings.pieces = settings.pieces.take().map(|x| base.join(x));
+    settings.checkpoint = settings.checkpoint.take().map(|x| base.join(x));
+    settings.output_dir = settings.output_dir.take().map(|x| base.join(x));
+    for sink in settings.sinks.iter_mut().flatten() {
+        if let SinkSpec::Jsonl(path) = sink {
+            *path = base.join(&path);
+        }
+    }
+}
+
+/// Makes a path absolute so that the record of a run still points at it from elsewhere.
+fn absolute(path: PathBuf) -> Result<PathBuf, String> {
+    path::absolute(&path).map_err(|e| format!("Invalid path {}: {e}", path.display()))
+}
@@ -1,3 +1,8 @@
+use crate::board_order::default_board_order;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::str::FromStr;
+
 pub const MIN_SOLVE_INDEX_TO_SAVE: usize = 252;
 // pub const MAX_NODE_COUNT: u64 = 50_000_000_000;
 pub const MAX_NODE_COUNT: u64 = 500_000_000;
@@ -5,3 +10,120 @@ pub const HEURISTIC_SIDES: &[u8] = &[13, 16, 10];
 pub const BREAK_INDEXES_ALLOWED: &[usize] = &[201, 206, 211, 216, 221, 225, 229, 233, 237, 239];
 
 pub const MAX_HEURISTIC_INDEX: usize = 160;
+pub const HEURISTIC_CURVE: &[HeuristicSegment] = &[
+    HeuristicSegment::new(16, 16, 0.0, 0.0),
+    HeuristicSegment::new(26, 16, 2.8, 0.0),
+    HeuristicSegment::new(56, 26, 1.43333, 28.0),
+    HeuristicSegment::new(76, 56, 0.9, 71.0),
+    HeuristicSegment::new(102, 76, 0.6538, 89.0),
+    HeuristicSegment::new(255, 102, 0.22414, 106.0),
+];
+
+/// Nodes between checkpoints, a multiple of `PROGRESS_NODE_INTERVAL`.
+pub const CHECKPOINT_NODE_INTERVAL: u64 = 1 << 27;
+/// Nodes between publishing progress and checking for a stop. A power of two, so that the search
+/// can test for it with a mask.
+pub const PROGRESS_NODE_INTERVAL: u64 = 1 << 20;
+
+/// How many placements in the search order make up a board's prefix in the result store.
+pub const PREFIX_LENGTH: usize = 16;
+
+/// One straight piece of the heuristic curve: positions in the search order up to and including
+/// `until` need `(position - from) * slope + offset` heuristic sides placed, rounded down.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct HeuristicSegment {
+    pub until: usize,
+    pub from: usize,
+    pub slope: f32,
+    pub offset: f32,
+}
+
+impl HeuristicSegment {
+    pub const fn new(until: usize, from: usize, slope: f32, offset: f32) -> HeuristicSegment {
+        HeuristicSegment {
+            until,
+            from,
+            slope,
+            offset,
+        }
+    }
+}
+
+/// How saved boards are written to the output directory.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// One `.txt` file per board: the grid, the e2.bucas.name link and the seeds.
+    #[default]
+    Text,
+    /// One `.json` file per board.
+    Json,
+    /// One JSON line per board, appended to `boards.jsonl`.
+    Jsonl,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<OutputFormat, String> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "jsonl" => Ok(OutputFormat::Jsonl),
+            _ => Err(format!(
+                "unknown output format {s:?}, expected text, json or jsonl"
+            )),
+        }
+    }
+}
+
+/// The tuning that shapes the candidate tables and the search, and where its boards are saved.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SolverConfig {
+    /// Boards at least this deep are saved.
+    pub min_solve_index_to_save: usize,
+    /// Directory that saved boards are written to.
+    pub output_dir: PathBuf,
+    pub output_format: OutputFormat,
+    /// Name of the file in `output_dir` that records the settings of the run, noted in every
+    /// saved board.
+    pub profile: Option<String>,
+    /// Position in the search order of each cell, one row per line with the top row first.
+    pub board_order: Vec<Vec<usize>>,
+    /// Colours whose placement the heuristic rewards early in the search.
+    pub heuristic_sides: Vec<u8>,
+    /// Positions in the search order at which one more break becomes allowed.
+    pub break_indexes_allowed: Vec<usize>,
+    /// The last position in the search order at which the heuristic is enforced.
+    pub max_heuristic_index: usize,
+    /// How many heuristic sides must be placed by each position up to `max_heuristic_index`.
+    /// Positions past the last segment need none.
+    pub heuristic_curve: Vec<HeuristicSegment>,
+}
+
+impl SolverConfig {
+    /// Returns the settings above for a board of this size, saving to `~/EternitySolutions`. The
+    /// break positions and heuristic curve are tuned for the 16x16 board, so other sizes get
+    /// neither.
+    pub fn for_board(width: usize, height: usize) -> SolverConfig {
+        let num_cells = width * height;
+        let tuned = num_cells == 256;
+        SolverConfig {
+            min_solve_index_to_save: MIN_SOLVE_INDEX_TO_SAVE.min(num_cells),
+            output_dir: dirs::home_dir()
+                .unwrap_or_default()
+                .join("EternitySolutions"),
+            output_format: OutputFormat::Text,
+            profile: None,
+            board_order: default_board_order(width, height),
+            heuristic_sides: HEURISTIC_SIDES.to_vec(),
+            break_indexes_allowed: if tuned {
+                BREAK_INDEXES_ALLOWED.to_vec()
+            } else {
+                vec![]
+            },
+            max_heuristic_index: if tuned { MAX_HEURISTIC_INDEX } else { 0 },
+            heuristic_curve: HEURISTIC_CURVE.to_vec(),
+        }
+    }
+}
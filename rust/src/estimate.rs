@@ -0,0 +1,297 @@
+use crate::run::Settings;
+use crate::solve_puzzle::SearchState;
+use crate::solver_data::{prepare_pieces_and_heuristics, SolverData};
+use crate::util::derive_seed;
+use log::info;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::sync::Arc;
+use std::time::Instant;
+use thousands::Separable;
+
+/// How many standard errors either side of the mean a 95% confidence interval spans.
+const Z_95: f64 = 1.96;
+
+/// Estimates the size of the search tree by Knuth's method: each probe walks from the empty
+/// board down a random path, choosing uniformly among the pieces the search would try, and the
+/// product of the numbers of choices along the way is an unbiased estimate of the number of
+/// boards at each depth. Probes are added to running totals, which can be merged across threads.
+pub struct Estimator {
+    probes: u64,
+    /// The mean estimate of the boards at each depth, and at the end the total.
+    mean: Vec<f64>,
+    /// The sum of squared differences from the mean, for the variance.
+    m2: Vec<f64>,
+    /// How many probes reached each depth.
+    reached: Vec<u64>,
+}
+
+/// The estimated number of boards with `depth` pieces placed that the search can reach.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DepthEstimate {
+    pub depth: usize,
+    pub mean: f64,
+    /// Half the width of the 95% confidence interval around `mean`.
+    pub half_width: f64,
+    /// How many probes got this deep. The interval is only as good as this is large.
+    pub reached: u64,
+}
+
+/// Estimates for every depth and for the whole tree.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TreeEstimate {
+    pub probes: u64,
+    /// One estimate per depth, from the empty board to the full one.
+    pub depths: Vec<DepthEstimate>,
+    /// The estimated number of boards at every depth together. Its `depth` is the board size.
+    pub total: DepthEstimate,
+}
+
+impl Estimator {
+    pub fn new(num_cells: usize) -> Estimator {
+        Estimator {
+            probes: 0,
+            mean: vec![0.0; num_cells + 2],
+            m2: vec![0.0; num_cells + 2],
+            reached: vec![0; num_cells + 1],
+        }
+    }
+
+    /// Runs one probe and adds it to the totals. `state` must hold an empty board, such as one
+    /// from `SearchState::from_prefix` with no pieces, and is left empty again.
+    pub fn probe(&mut self, solver_data: &SolverData, state: &mut SearchState, rng: &mut StdRng) {
+        let num_cells = solver_data.puzzle.num_cells();
+        let mut sample = vec![0.0; num_cells + 2];
+        let mut boards = 1.0;
+        sample[0] = boards;
+        while state.solve_index < num_cells {
+            let candidates = state.candidates(solver_data);
+            if candidates.is_empty() {
+                break;
+            }
+            boards *= candidates.len() as f64;
+            state.place(
+                solver_data,
+                candidates[rng.random_range(0..candidates.len())],
+            );
+            sample[state.solve_index] = boards;
+        }
+        let depth = state.solve_index;
+        while state.solve_index > 0 {
+            state.take_back(solver_data);
+        }
+        sample[num_cells + 1] = sample[..=num_cells].iter().sum();
+        self.add(&sample, depth);
+    }
+
+    /// Adds a probe that got `depth` pieces deep, with its estimate at each depth and the total.
+    fn add(&mut self, sample: &[f64], depth: usize) {
+        for reached in &mut self.reached[..=depth] {
+            *reached += 1;
+        }
+
+        // Welford's method, which keeps the variance accurate over many probes.
+        self.probes += 1;
+        let n = self.probes as f64;
+        for ((x, mean), m2) in sample.iter().zip(&mut self.mean).zip(&mut self.m2) {
+            let delta = x - *mean;
+            *mean += delta / n;
+            *m2 += delta * (x - *mean);
+        }
+    }
+
+    /// Adds the probes of another estimator of the same tree.
+    pub fn merge(&mut self, other: &Estimator) {
+        if other.probes == 0 {
+            return;
+        }
+        let (n_a, n_b) = (self.probes as f64, other.probes as f64);
+        let n = n_a + n_b;
+        for i in 0..self.mean.len() {
+            let delta = other.mean[i] - self.mean[i];
+            self.mean[i] += delta * n_b / n;
+            self.m2[i] += other.m2[i] + delta * delta * n_a * n_b / n;
+        }
+        for (reached, other) in self.reached.iter_mut().zip(&other.reached) {
+            *reached += other;
+        }
+        self.probes += other.probes;
+    }
+
+    pub fn estimate(&self) -> TreeEstimate {
+        let num_cells = self.reached.len() - 1;
+        let half_width = |i: usize| {
+            if self.probes < 2 {
+                return f64::INFINITY;
+            }
+            let variance = self.m2[i] / (self.probes - 1) as f64;
+            Z_95 * (variance / self.probes as f64).sqrt()
+        };
+        TreeEstimate {
+            probes: self.probes,
+            depths: (0..=num_cells)
+                .map(|depth| DepthEstimate {
+                    depth,
+                    mean: self.mean[depth],
+                    half_width: half_width(depth),
+                    reached: self.reached[depth],
+                })
+                .collect(),
+            total: DepthEstimate {
+                depth: num_cells,
+                mean: self.mean[num_cells + 1],
+                half_width: half_width(num_cells + 1),
+                reached: self.probes,
+            },
+        }
+    }
+}
+
+/// Runs `probes` probes on one thread from `seed`.
+pub fn estimate_tree(solver_data: &SolverData, probes: u64, seed: u64) -> Estimator {
+    let mut estimator = Estimator::new(solver_data.puzzle.num_cells());
+    let mut state = SearchState::from_prefix(solver_data, 0, &[]);
+    let mut rng = StdRng::seed_from_u64(seed);
+    for _ in 0..probes {
+        estimator.probe(solver_data, &mut state, &mut rng);
+    }
+    estimator
+}
+
+/// Estimates the size of the search tree at each depth from `probes` random probes.
+pub fn run_estimate(probes: u64, settings: Settings) {
+    let threads = settings.threads.max(1);
+    let master_seed = settings.seed;
+    let solver_data = Arc::new(prepare_pieces_and_heuristics(
+        &settings.puzzle,
+        &settings.config,
+        derive_seed(master_seed, &[1]),
+    ));
+
+    info!(
+        "Running {} probes on {threads} threads with seed {master_seed}...",
+        probes.separate_with_commas()
+    );
+    let stopwatch = Instant::now();
+    let handles: Vec<_> = (0..threads)
+        .map(|thread| {
+            let solver_data = Arc::clone(&solver_data);
+            // The first threads take one more probe each to make up the total.
+            let count =
+                probes / threads as u64 + u64::from((thread as u64) < probes % threads as u64);
+            let seed = derive_seed(master_seed, &[1, thread as u64]);
+            std::thread::spawn(move || estimate_tree(&solver_data, count, seed))
+        })
+        .collect();
+    let mut estimator = Estimator::new(settings.puzzle.num_cells());
+    for handle in handles {
+        estimator.merge(&handle.join().unwrap());
+    }
+    let estimate = estimator.estimate();
+
+    for depth in estimate.depths.iter().filter(|x| x.mean > 0.0) {
+        println!(
+            "{} {:.3e} ± {:.3e}, reached by {}",
+            depth.depth,
+            depth.mean,
+            depth.half_width,
+            depth.reached.separate_with_commas()
+        );
+    }
+    let total = &estimate.total;
+    info!(
+        "Total {:.3e} ± {:.3e} boards at 95% confidence from {} probes in {:.2} seconds",
+        total.mean,
+        total.half_width,
+        estimate.probes.separate_with_commas(),
+        stopwatch.elapsed().as_secs_f64()
+    );
+    if let Some(deepest) = estimate.depths.iter().rev().find(|x| x.reached > 0) {
+        info!(
+            "The deepest probes placed {} pieces, reached by {}",
+            deepest.depth,
+            deepest.reached.separate_with_commas()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SolverConfig;
+    use crate::generate::generate_puzzle;
+    use crate::solver_data::prepare_pieces_and_heuristics;
+
+    fn estimator(samples: &[f64]) -> Estimator {
+        let mut estimator = Estimator::new(0);
+        for &x in samples {
+            estimator.add(&[x, x], 0);
+        }
+        estimator
+    }
+
+    #[test]
+    fn intervals_come_from_the_sample_variance() {
+        let estimate = estimator(&[2.0, 4.0, 4.0, 6.0]).estimate();
+        assert_eq!(estimate.probes, 4);
+        assert_eq!(estimate.total.mean, 4.0);
+        // A sample variance of 8/3 over 4 probes.
+        let half_width = Z_95 * (8.0f64 / 3.0 / 4.0).sqrt();
+        assert!((estimate.total.half_width - half_width).abs() < 1e-12);
+
+        assert_eq!(estimator(&[5.0]).estimate().total.half_width, f64::INFINITY);
+    }
+
+    #[test]
+    fn merged_estimators_match_one_that_saw_every_probe() {
+        let samples = [1.0, 7.0, 3.5, 2.0, 100.0, 0.0, 8.25];
+        let whole = estimator(&samples).estimate();
+        for split in 0..samples.len() {
+            let mut merged = estimator(&samples[..split]);
+            merged.merge(&estimator(&samples[split..]));
+            let merged = merged.estimate();
+            assert_eq!(merged.probes, whole.probes);
+            assert_eq!(merged.total.reached, whole.total.reached);
+            assert!((merged.total.mean - whole.total.mean).abs() < 1e-9);
+            assert!((merged.total.half_width - whole.total.half_width).abs() < 1e-9);
+        }
+    }
+
+    /// Counts the boards at each depth below the state's by trying every candidate in turn.
+    fn count_boards(solver_data: &SolverData, state: &mut SearchState, counts: &mut [u64]) {
+        counts[state.solve_index] += 1;
+        if state.solve_index == solver_data.puzzle.num_cells() {
+            return;
+        }
+        for candidate in state.candidates(solver_data) {
+            state.place(solver_data, candidate);
+            count_boards(solver_data, state, counts);
+            state.take_back(solver_data);
+        }
+    }
+
+    #[test]
+    fn probes_estimate_the_boards_at_each_depth() {
+        let puzzle = generate_puzzle(4, 4, 2, 3, 5).unwrap().puzzle;
+        let config = SolverConfig::for_board(4, 4);
+        let solver_data = prepare_pieces_and_heuristics(&puzzle, &config, 1);
+        let mut exact = vec![0; 17];
+        let mut state = SearchState::from_prefix(&solver_data, 0, &[]);
+        count_boards(&solver_data, &mut state, &mut exact);
+
+        let mut estimator = estimate_tree(&solver_data, 2_000, 1);
+        estimator.merge(&estimate_tree(&solver_data, 2_000, 2));
+        let estimate = estimator.estimate();
+        assert_eq!(estimate.probes, 4_000);
+        assert_eq!(
+            (estimate.depths[1].mean, estimate.depths[1].half_width),
+            (exact[1] as f64, 0.0)
+        );
+        // With these seeds every interval holds the true count.
+        let within = |x: &DepthEstimate, exact: u64| (x.mean - exact as f64).abs() <= x.half_width;
+        for depth in &estimate.depths {
+            assert!(within(depth, exact[depth.depth]), "{depth:?}, {exact:?}");
+        }
+        assert!(within(&estimate.total, exact.iter().sum()));
+    }
+}
@@ -0,0 +1,352 @@
+//! Exhaustive runs, which search every board below each prefix of the search order instead of
+//! sampling the tree at random.
+
+use crate::checkpoint::CoverageCheckpoint;
+use crate::prefixes::{enumerate_prefixes, prefixes_hash};
+use crate::progress::Progress;
+use crate::run::{
+    flush_sinks, open_checkpoint, open_sinks, report, watch_progress, BestBoard, Settings, STOP,
+};
+use crate::sinks::ResultSink;
+use crate::solutions::{num_symmetries, SolutionCollector};
+use crate::solve_puzzle::{solve_puzzle, SearchState};
+use crate::solver_data::{prepare_pieces_and_heuristics, SolverData};
+use crate::structs::{RotatedPiece, SolverResult};
+use crate::util::derive_seed;
+use log::{info, warn};
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use thousands::Separable;
+
+/// How often an exhaustive run reports and checkpoints its coverage.
+const COVERAGE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Searches every board below each prefix of the search order `prefix_depth` placements long,
+/// recording which prefixes have been searched to the end, until they all have or the process is
+/// stopped.
+pub fn run_exhaust(prefix_depth: usize, settings: Settings) -> Result<(), String> {
+    if prefix_depth == 0 {
+        return Err(
+            "A prefix starts with the first corner, so --prefix-depth must be at least 1".into(),
+        );
+    }
+    if settings.threads == 0 {
+        return Err("Nothing would search without threads".into());
+    }
+    let sinks = open_sinks(&settings)?;
+    let threads = settings.threads;
+    let puzzle = &settings.puzzle;
+    let num_cells = puzzle.num_cells();
+    let progress = Arc::new(Progress::new(threads, num_cells));
+    watch_progress(&progress, &settings)?;
+    let checkpoint = settings
+        .checkpoint
+        .as_deref()
+        .map(open_checkpoint)
+        .transpose()?;
+    let max_node_count = settings.max_node_count;
+    let solver_data = Arc::new(prepare_pieces_and_heuristics(
+        puzzle,
+        &settings.config,
+        derive_seed(settings.seed, &[1]),
+    ));
+
+    let stopwatch = Instant::now();
+    let prefixes = Arc::new(enumerate_prefixes(&solver_data, prefix_depth));
+    let prefix_depth = prefix_depth.min(num_cells);
+    info!(
+        "Found {} prefixes {prefix_depth} pieces long in {:.2} seconds",
+        prefixes.len().separate_with_commas(),
+        stopwatch.elapsed().as_secs_f64()
+    );
+    let mut coverage = CoverageCheckpoint {
+        prefix_depth,
+        prefixes_hash: prefixes_hash(&prefixes),
+        num_prefixes: prefixes.len(),
+        max_node_count,
+        finished: vec![],
+        cut_off: vec![],
+        index_counts: vec![0; num_cells + 1],
+        cut_off_counts: vec![0; num_cells + 1],
+        max_depth: 0,
+    };
+    if let Some(checkpoint) = &checkpoint {
+        match checkpoint.load_coverage() {
+            Ok(Some(mut loaded)) if loaded.prefixes_hash == coverage.prefixes_hash => {
+                if loaded.max_node_count < max_node_count && !loaded.cut_off.is_empty() {
+                    info!(
+                        "Searching {} cut off prefixes again with the larger node budget",
+                        loaded.cut_off.len().separate_with_commas()
+                    );
+                    // Their searches start again from the top, so what they counted goes.
+                    for (total, count) in loaded.index_counts.iter_mut().zip(&loaded.cut_off_counts)
+                    {
+                        *total -= count;
+                    }
+                    loaded.cut_off.clear();
+                    loaded.cut_off_counts = vec![0; num_cells + 1];
+                    loaded.max_node_count = max_node_count;
+                }
+                loaded.cut_off_counts.resize(num_cells + 1, 0);
+                coverage = loaded;
+                info!(
+                    "Resuming with {} prefixes finished and {} cut off",
+                    coverage.finished.len().separate_with_commas(),
+                    coverage.cut_off.len().separate_with_commas()
+                );
+            }
+            Ok(Some(_)) => warn!("Ignoring coverage checkpoint for different prefixes"),
+            Ok(None) => {}
+            Err(e) => warn!("Failed to read coverage checkpoint: {e}"),
+        }
+    }
+    let searched: HashSet<usize> = coverage
+        .finished
+        .iter()
+        .chain(&coverage.cut_off)
+        .copied()
+        .collect();
+    let pending: Vec<usize> = (0..prefixes.len())
+        .filter(|x| !searched.contains(x))
+        .collect();
+
+    info!(
+        "Searching {} prefixes on {threads} threads...",
+        pending.len().separate_with_commas()
+    );
+    let results = search_prefixes(
+        &solver_data,
+        &prefixes,
+        pending,
+        max_node_count,
+        Arc::clone(&sinks.all) as Arc<dyn ResultSink>,
+        &progress,
+    );
+
+    // Everything counted, including the searches that were stopped part way, for the report.
+    let mut index_counts = coverage.index_counts.clone();
+    let mut max_depth = coverage.max_depth;
+    let mut best_board: Option<BestBoard> = None;
+    let mut last_save = Instant::now();
+    let save = |coverage: &CoverageCheckpoint| {
+        if let Some(checkpoint) = &checkpoint {
+            if let Err(e) = checkpoint.save_coverage(coverage) {
+                warn!("Failed to write coverage checkpoint: {e}");
+            }
+        }
+    };
+    // The results stop coming once every thread has stopped.
+    for (thread, index, solver_result) in results {
+        for (total, count) in index_counts.iter_mut().zip(&solver_result.solve_indexes) {
+            *total += count;
+        }
+        max_depth = max_depth.max(solver_result.max_depth);
+        let is_better = best_board.as_ref().is_none_or(|best| {
+            (
+                solver_result.max_depth,
+                solver_result.best_score.matched_edges,
+            ) > (best.depth, best.score.matched_edges)
+        });
+        if is_better {
+            best_board = Some(BestBoard {
+                board: solver_result.best_board.clone(),
+                score: solver_result.best_score,
+                depth: solver_result.max_depth,
+                core: thread,
+                search: format!("prefix {index}"),
+            });
+        }
+        // A stopped search is started again from its prefix next time.
+        if solver_result.stopped {
+            continue;
+        }
+
+        if solver_result.exhausted {
+            coverage.finished.push(index);
+        } else {
+            coverage.cut_off.push(index);
+            for (total, count) in coverage
+                .cut_off_counts
+                .iter_mut()
+                .zip(&solver_result.solve_indexes)
+            {
+                *total += count;
+            }
+        }
+        for (total, count) in coverage
+            .index_counts
+            .iter_mut()
+            .zip(&solver_result.solve_indexes)
+        {
+            *total += count;
+        }
+        coverage.max_depth = coverage.max_depth.max(solver_result.max_depth);
+        if last_save.elapsed() >= COVERAGE_INTERVAL {
+            last_save = Instant::now();
+            save(&coverage);
+            report_coverage(&coverage);
+        }
+    }
+    save(&coverage);
+
+    info!("Result");
+    flush_sinks(&sinks);
+    report(
+        puzzle,
+        &index_counts,
+        max_depth,
+        best_board.as_ref(),
+        &stopwatch,
+    );
+    report_coverage(&coverage);
+    Ok(())
+}
+
+/// Searches every board below each of the `pending` prefixes, sending them to `sink`, on one
+/// thread for each worker in `progress`. The result of each search comes back with the thread
+/// that ran it and the index of its prefix, until they have all been searched or the run is
+/// stopped.
+pub fn search_prefixes(
+    solver_data: &Arc<SolverData>,
+    prefixes: &Arc<Vec<Vec<RotatedPiece>>>,
+    pending: Vec<usize>,
+    max_node_count: u64,
+    sink: Arc<dyn ResultSink>,
+    progress: &Arc<Progress>,
+) -> Receiver<(usize, usize, SolverResult)> {
+    let threads = progress.snapshot().workers.len();
+    let pending = Arc::new(pending);
+    let next = Arc::new(AtomicUsize::new(0));
+    let (sender, results) = mpsc::channel();
+    for thread in 0..threads {
+        let solver_data = Arc::clone(solver_data);
+        let sink = Arc::clone(&sink);
+        let progress = Arc::clone(progress);
+        let (prefixes, pending, next) = (
+            Arc::clone(prefixes),
+            Arc::clone(&pending),
+            Arc::clone(&next),
+        );
+        let sender = sender.clone();
+        std::thread::spawn(move || {
+            while !STOP.load(Ordering::Relaxed) {
+                let Some(&index) = pending.get(next.fetch_add(1, Ordering::Relaxed)) else {
+                    break;
+                };
+                let state = SearchState::from_prefix(&solver_data, thread, &prefixes[index]);
+                let solver_result = solve_puzzle(
+                    &solver_data,
+                    state,
+                    max_node_count,
+                    Some(&sink),
+                    Some(&progress.worker(thread)),
+                    Some(&STOP),
+                    None,
+                );
+                if sender.send((thread, index, solver_result)).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+    results
+}
+
+/// Counts every solution of the puzzle by searching below every prefix `prefix_depth` placements
+/// long, and when `write` is set sends each distinct one to the sinks. Solutions that are the
+/// same board turned round count as one distinct solution.
+pub fn run_count(prefix_depth: usize, write: bool, mut settings: Settings) -> Result<(), String> {
+    if prefix_depth == 0 {
+        return Err(
+            "A prefix starts with the first corner, so --prefix-depth must be at least 1".into(),
+        );
+    }
+    if settings.threads == 0 {
+        return Err("Nothing would search without threads".into());
+    }
+    let sinks = write.then(|| open_sinks(&settings)).transpose()?;
+    let threads = settings.threads;
+    let puzzle = &settings.puzzle;
+    let num_cells = puzzle.num_cells();
+    let progress = Arc::new(Progress::new(threads, num_cells));
+    watch_progress(&progress, &settings)?;
+    // Only full boards are wanted.
+    settings.config.min_solve_index_to_save = num_cells;
+    let solver_data = Arc::new(prepare_pieces_and_heuristics(
+        puzzle,
+        &settings.config,
+        derive_seed(settings.seed, &[1]),
+    ));
+
+    let stopwatch = Instant::now();
+    let prefixes = Arc::new(enumerate_prefixes(&solver_data, prefix_depth));
+    info!(
+        "Counting solutions below {} prefixes on {threads} threads...",
+        prefixes.len().separate_with_commas()
+    );
+    let output = sinks
+        .as_ref()
+        .map(|x| Box::new(Arc::clone(&x.all)) as Box<dyn ResultSink>);
+    let collector = Arc::new(SolutionCollector::new(puzzle, output));
+    let results = search_prefixes(
+        &solver_data,
+        &prefixes,
+        (0..prefixes.len()).collect(),
+        settings.max_node_count,
+        Arc::clone(&collector) as Arc<dyn ResultSink>,
+        &progress,
+    );
+
+    let mut nodes: u64 = 0;
+    let mut unfinished = 0;
+    for (_, _, solver_result) in results {
+        nodes += solver_result.solve_indexes.iter().sum::<u64>();
+        if !solver_result.exhausted {
+            unfinished += 1;
+        }
+    }
+    if let Some(sinks) = &sinks {
+        flush_sinks(sinks);
+    }
+
+    info!(
+        "Found {} solutions in {} nodes and {:.2} seconds",
+        collector.found().separate_with_commas(),
+        nodes.separate_with_commas(),
+        stopwatch.elapsed().as_secs_f64()
+    );
+    info!(
+        "{} distinct solutions, counting the {} ways of turning each board as one",
+        collector.distinct().separate_with_commas(),
+        num_symmetries(puzzle)
+    );
+    if unfinished > 0 || STOP.load(Ordering::Relaxed) {
+        warn!(
+            "The count is incomplete: {} prefixes were cut off at the node budget or stopped",
+            unfinished.separate_with_commas()
+        );
+    }
+    Ok(())
+}
+
+/// Reports how many of an exhaustive run's prefixes have been searched to the end.
+pub fn report_coverage(coverage: &CoverageCheckpoint) {
+    let finished = coverage.finished.len();
+    let cut_off = coverage.cut_off.len();
+    let total = coverage.num_prefixes;
+    info!(
+        "Coverage: {} of {} prefixes {} pieces long searched to the end ({:.3}%), {} cut off at the node budget, {} not yet searched",
+        finished.separate_with_commas(),
+        total.separate_with_commas(),
+        coverage.prefix_depth,
+        100.0 * finished as f64 / total.max(1) as f64,
+        cut_off.separate_with_commas(),
+        (total - finished - cut_off).separate_with_commas()
+    );
+    if finished == total {
+        info!("Every board the search can reach has been searched");
+    }
+}
@@ -0,0 +1,192 @@
+use crate::piece_file::{format_pieces, normalise_orientation};
+use crate::puzzle::Puzzle;
+use crate::structs::{Piece, RotatedPiece};
+use crate::util::{board_url, format_board};
+use log::info;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use std::fs;
+use std::path::Path;
+
+/// The most colours a puzzle can have besides grey, since a board link writes each colour as one
+/// letter, grey as `a` and the others from `b` to `z`.
+pub const MAX_COLOURS: usize = 25;
+
+/// A random puzzle and the solution it was cut from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GeneratedPuzzle {
+    pub puzzle: Puzzle,
+    /// The planted solution, indexed by `row * width + column` from the bottom left.
+    pub solution: Vec<RotatedPiece>,
+}
+
+/// Builds a solvable `width` by `height` puzzle from `seed`. Every edge between two border
+/// pieces gets one of `border_colours` colours, numbered from 1, and every other inner edge one
+/// of `interior_colours`, numbered after them. The pieces are shuffled and the middles turned at
+/// random, while corners and sides keep their grey edges down as the solver expects. Since
+/// `Puzzle` lays boards out wider than they are tall, a taller board is generated on its side.
+pub fn generate_puzzle(
+    width: usize,
+    height: usize,
+    border_colours: u8,
+    interior_colours: u8,
+    seed: u64,
+) -> Result<GeneratedPuzzle, String> {
+    let (width, height) = (width.max(height), width.min(height));
+    if height < 3 {
+        return Err(format!(
+            "a {width}x{height} board is too narrow, since it needs at least 3 rows and columns"
+        ));
+    }
+    if border_colours == 0 || interior_colours == 0 {
+        return Err("the board needs at least one border and one interior colour".to_string());
+    }
+    if border_colours as usize + interior_colours as usize > MAX_COLOURS {
+        return Err(format!(
+            "{} colours is more than a board link can show, which is {MAX_COLOURS}",
+            border_colours as usize + interior_colours as usize
+        ));
+    }
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut colour = |border: bool| {
+        if border {
+            rng.random_range(1..=border_colours)
+        } else {
+            border_colours + rng.random_range(1..=interior_colours)
+        }
+    };
+    // The colour on the right of each cell and on its top, 0 at the edge of the board.
+    let mut right = vec![0u8; width * height];
+    let mut top = vec![0u8; width * height];
+    for row in 0..height {
+        for col in 0..width {
+            // Edges along the bottom and top rows and up the first and last columns join two
+            // border pieces.
+            if col + 1 < width {
+                right[row * width + col] = colour(row == 0 || row == height - 1);
+            }
+            if row + 1 < height {
+                top[row * width + col] = colour(col == 0 || col == width - 1);
+            }
+        }
+    }
+
+    // The pieces as they sit in the solution, before they are shuffled and turned.
+    let mut placed: Vec<Piece> = (0..height)
+        .flat_map(|row| (0..width).map(move |col| (row, col)))
+        .map(|(row, col)| Piece {
+            reid: 0,
+            top: top[row * width + col],
+            right: right[row * width + col],
+            bottom: if row == 0 {
+                0
+            } else {
+                top[(row - 1) * width + col]
+            },
+            left: if col == 0 {
+                0
+            } else {
+                right[row * width + col - 1]
+            },
+        })
+        .collect();
+
+    let mut order: Vec<usize> = (0..placed.len()).collect();
+    order.shuffle(&mut rng);
+    let mut pieces = vec![];
+    for (i, &cell) in order.iter().enumerate() {
+        placed[cell].reid = (i + 1) as u16;
+        let turned = placed[cell].rotated(rng.random_range(0..4));
+        pieces.push(normalise_orientation(turned)?);
+    }
+
+    let solution = placed
+        .iter()
+        .map(|x| {
+            let piece = pieces[x.reid as usize - 1];
+            let rotations = (0..4).find(|&r| piece.rotated(r) == *x).unwrap();
+            RotatedPiece {
+                reid: x.reid,
+                rotations,
+                top: x.top,
+                right: x.right,
+                ..RotatedPiece::default()
+            }
+        })
+        .collect();
+    let puzzle = Puzzle::from_pieces(pieces)?;
+    Ok(GeneratedPuzzle { puzzle, solution })
+}
+
+/// Builds a random puzzle as `generate_puzzle` does, and writes its pieces to `output`, or to
+/// stdout, and its planted solution to `solution` if given.
+pub fn run_generate(
+    width: usize,
+    height: usize,
+    border_colours: u8,
+    interior_colours: u8,
+    seed: u64,
+    output: Option<&Path>,
+    solution: Option<&Path>,
+) -> Result<(), String> {
+    let generated = generate_puzzle(width, height, border_colours, interior_colours, seed)
+        .map_err(|e| format!("Failed to generate a puzzle: {e}"))?;
+    let puzzle = &generated.puzzle;
+    info!(
+        "Generated a {}x{} puzzle with {} colours from seed {seed}",
+        puzzle.width, puzzle.height, puzzle.num_colours
+    );
+
+    let pieces = format!(
+        "# {}x{} puzzle generated from seed {seed}\n{}",
+        puzzle.width,
+        puzzle.height,
+        format_pieces(&puzzle.pieces)
+    );
+    match output {
+        Some(path) => {
+            fs::write(path, pieces)
+                .map_err(|e| format!("Failed to write {}: {e}", path.display()))?;
+            info!("Pieces written to {}", path.display());
+        }
+        None => print!("{pieces}"),
+    }
+    if let Some(path) = solution {
+        let text = format!(
+            "{}\n{}\n",
+            format_board(puzzle, &generated.solution),
+            board_url(puzzle, &generated.solution)
+        );
+        fs::write(path, text).map_err(|e| format!("Failed to write {}: {e}", path.display()))?;
+        info!("Solution written to {}", path.display());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board_file::parse_board_link;
+
+    #[test]
+    fn links_show_every_colour_up_to_the_limit() {
+        let generated = generate_puzzle(6, 6, 5, 20, 3).unwrap();
+        let puzzle = &generated.puzzle;
+        assert_eq!(puzzle.num_colours, MAX_COLOURS + 1);
+        let link = board_url(puzzle, &generated.solution);
+        let linked_board = parse_board_link(puzzle, &link).unwrap();
+        assert!(linked_board.unidentified.is_empty());
+        let key = |board: &[RotatedPiece]| -> Vec<(u16, u8)> {
+            board.iter().map(|x| (x.reid, x.rotations)).collect()
+        };
+        assert_eq!(
+            key(&linked_board.board_file.board),
+            key(&generated.solution)
+        );
+
+        assert!(generate_puzzle(6, 6, 5, 21, 3).is_err());
+        assert!(generate_puzzle(6, 6, 200, 55, 3).is_err());
+    }
+}
@@ -0,0 +1,61 @@
+//! An Eternity II solver: a randomised depth-first search over edge-matching puzzles, with
+//! candidate tables tuned for the 16x16 Eternity II board.
+//!
+//! A search takes three steps: build a [`puzzle::Puzzle`] from a piece set, turn it into
+//! [`solver_data::SolverData`] with a [`config::SolverConfig`], then run
+//! [`solve_puzzle::solve_puzzle`] from a seeded [`solve_puzzle::SearchState`] with a node budget.
+//!
+//! ```
+//! use eternityii_solver::config::SolverConfig;
+//! use eternityii_solver::pieces::PIECES;
+//! use eternityii_solver::puzzle::Puzzle;
+//! use eternityii_solver::solve_puzzle::{solve_puzzle, SearchState};
+//! use eternityii_solver::solver_data::prepare_pieces_and_heuristics;
+//!
+//! let puzzle = Puzzle::from_pieces(PIECES.to_vec()).unwrap();
+//! let config = SolverConfig::for_board(puzzle.width, puzzle.height);
+//! let solver_data = prepare_pieces_and_heuristics(&puzzle, &config, 1);
+//! let state = SearchState::new(&solver_data, 0, 2);
+//! let result = solve_puzzle(&solver_data, state, 100_000, None, None, None, None);
+//!
+//! let placed = result.best_board.iter().filter(|x| x.reid > 0).count();
+//! assert_eq!(placed, result.max_depth);
+//! ```
+//!
+//! When a [`sinks::ResultSink`] is given, such as a [`result_store::ResultStore`], boards at
+//! least [`config::SolverConfig::min_solve_index_to_save`] deep are sent to it as the search
+//! finds them.
+//!
+//! Each command of the binary has a `run_` function here, such as [`solve::run_solve`] and
+//! [`exhaust::run_exhaust`], which takes the resolved [`run::Settings`] and stops when
+//! [`run::STOP`] is set.
+
+pub mod bench;
+mod bits;
+pub mod board_file;
+pub mod board_order;
+pub mod checkpoint;
+pub mod config;
+pub mod estimate;
+pub mod exhaust;
+pub mod generate;
+pub mod metrics;
+pub mod piece_file;
+pub mod pieces;
+pub mod pool;
+pub mod prefixes;
+pub mod progress;
+pub mod protocol;
+pub mod puzzle;
+pub mod remote;
+pub mod render;
+pub mod result_store;
+pub mod run;
+pub mod sinks;
+pub mod solutions;
+pub mod solve;
+pub mod solve_puzzle;
+pub mod solver_data;
+pub mod structs;
+pub mod util;
+pub mod verify;
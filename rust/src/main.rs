@@ -1,26 +1,45 @@
-use crate::solve_puzzle::solve_puzzle;
-use crate::solver_data::prepare_pieces_and_heuristics;
+use crate::cli::{Cli, Command, RunDefaults, SettingsArgs};
+use clap::Parser;
 use env_logger::{Builder, Env};
-use log::info;
+use eternityii_solver::bench::run_bench;
+use eternityii_solver::config::MAX_NODE_COUNT;
+use eternityii_solver::estimate::run_estimate;
+use eternityii_solver::exhaust::{run_count, run_exhaust};
+use eternityii_solver::generate::run_generate;
+use eternityii_solver::remote::work;
+use eternityii_solver::render::{run_render, RenderOptions};
+use eternityii_solver::run::{name_settings, record_settings, Settings, STOP};
+use eternityii_solver::solve::run_solve;
+use eternityii_solver::verify::{run_import, run_verify};
+use log::{error, warn};
 use std::env;
-use std::sync::{Arc, Mutex};
-use std::time::Instant;
-use thousands::Separable;
+use std::process;
+use std::sync::atomic::Ordering;
 
-mod bits;
-mod board_order;
-mod config;
-mod pieces;
-mod solve_puzzle;
-mod solver_data;
-mod structs;
-mod util;
+mod cli;
 
+const BENCH_NODE_COUNT: u64 = 100_000_000;
+const PROGRESS_INTERVAL: u64 = 60;
+/// No limit in practice, while still fitting in the TOML record of the settings.
+const EXHAUST_NODE_COUNT: u64 = i64::MAX as u64;
+
+/// The default number of threads: one fewer than the number of cores, to avoid grinding the
+/// system to a halt, but at least one.
 fn get_num_cores() -> usize {
-    // Save one core to avoid grinding the system to a halt.
-    match env::var("CORES") {
-        Ok(value) => value.parse::<usize>().unwrap() - 1,
-        Err(_e) => num_cpus::get() - 1,
+    num_cpus::get().saturating_sub(1).max(1)
+}
+
+/// Makes SIGINT and SIGTERM stop the workers at their next check, so that the run can report
+/// and checkpoint before it exits. A second signal exits at once.
+fn handle_signals() {
+    let result = ctrlc::set_handler(|| {
+        if STOP.swap(true, Ordering::Relaxed) {
+            process::exit(130);
+        }
+        warn!("Stopping the workers, signal again to exit at once");
+    });
+    if let Err(e) = result {
+        warn!("Failed to install the signal handler: {e}");
     }
 }
 
@@ -30,82 +49,136 @@ fn main() {
     builder.format_timestamp_millis();
     builder.init();
 
-    let num_virtual_cores = get_num_cores();
-    let overall_stopwatch = Instant::now();
-    let max_depth = Arc::new(Mutex::new(0));
-    let mut total_index_count: u64 = 0;
-    let mut loop_count: u64 = 0;
-    let index_counts = Arc::new(Mutex::new(vec![0u64; 257]));
-
-    loop {
-        loop_count += 1;
-
-        let solver_data = Arc::new(prepare_pieces_and_heuristics());
-
-        info!("Solving with {num_virtual_cores} cores...");
-
-        let mut handles = vec![];
-
-        for core in 0..num_virtual_cores {
-            let max_depth = Arc::clone(&max_depth);
-            let index_counts_clone = Arc::clone(&index_counts);
-            let solver_data_clone = Arc::clone(&solver_data);
-
-            let handle = std::thread::spawn(move || {
-                for repeat in 1..6 {
-                    info!("Core {core:02}: start loop {loop_count}, repeat {repeat}");
-                    let stopwatch = Instant::now();
-                    let solver_result = solve_puzzle(&solver_data_clone);
-
-                    let mut counts = index_counts_clone.lock().unwrap();
-                    for j in 0..257 {
-                        counts[j] += solver_result.solve_indexes[j];
-                    }
-                    drop(counts);
-
-                    {
-                        let mut max_depth = max_depth.lock().unwrap();
-                        if solver_result.max_depth > *max_depth {
-                            *max_depth = solver_result.max_depth;
-                        }
-                    }
-
-                    info!(
-                        "Core {core:02}: finish loop {loop_count}, repeat {repeat}, best depth {} in {} seconds",
-                        solver_result.max_depth,
-                        stopwatch.elapsed().as_secs().separate_with_commas()
-                    );
-                }
-            });
-
-            handles.push(handle);
+    let cli = Cli::parse();
+    let command = cli.command.unwrap_or(Command::Solve(cli.settings));
+    if matches!(
+        command,
+        Command::Solve(_)
+            | Command::Bench(_)
+            | Command::Exhaust(_)
+            | Command::Count(_)
+            | Command::Work(_)
+    ) {
+        handle_signals();
+    }
+    let result = match command {
+        Command::Solve(args) => run_solve(resolve_settings(
+            args,
+            RunDefaults {
+                threads: get_num_cores(),
+                seed: rand::random(),
+                max_node_count: MAX_NODE_COUNT,
+                progress_interval: PROGRESS_INTERVAL,
+            },
+            true,
+        )),
+        Command::Bench(args) => run_bench(
+            args.results,
+            resolve_settings(
+                args.settings,
+                RunDefaults {
+                    threads: 1,
+                    seed: 0,
+                    max_node_count: BENCH_NODE_COUNT,
+                    progress_interval: 0,
+                },
+                false,
+            ),
+        ),
+        Command::Exhaust(args) => run_exhaust(
+            args.prefix_depth,
+            resolve_settings(
+                args.settings,
+                RunDefaults {
+                    threads: get_num_cores(),
+                    seed: 0,
+                    max_node_count: EXHAUST_NODE_COUNT,
+                    progress_interval: PROGRESS_INTERVAL,
+                },
+                true,
+            ),
+        ),
+        Command::Estimate(args) => {
+            run_estimate(
+                args.probes,
+                resolve_settings(
+                    args.settings,
+                    RunDefaults {
+                        threads: get_num_cores(),
+                        seed: 0,
+                        max_node_count: MAX_NODE_COUNT,
+                        progress_interval: 0,
+                    },
+                    false,
+                ),
+            );
+            Ok(())
         }
-
-        // Wait for all threads to complete
-        for handle in handles {
-            handle.join().unwrap();
+        Command::Count(args) => run_count(
+            args.prefix_depth,
+            args.write,
+            resolve_settings(
+                args.settings,
+                RunDefaults {
+                    threads: get_num_cores(),
+                    seed: 0,
+                    max_node_count: EXHAUST_NODE_COUNT,
+                    progress_interval: 0,
+                },
+                true,
+            ),
+        ),
+        Command::Verify(args) => args
+            .puzzle()
+            .and_then(|puzzle| run_verify(&puzzle, &args.files)),
+        Command::Import(args) => args
+            .puzzle()
+            .and_then(|puzzle| run_import(&puzzle, &args.link, args.output.as_deref())),
+        Command::Render(args) => args.puzzle().and_then(|puzzle| {
+            let options = RenderOptions {
+                cell_size: args.cell_size,
+                show_ids: args.ids,
+                show_rotations: args.rotations,
+                highlight_breaks: !args.no_breaks,
+            };
+            run_render(&puzzle, &args.file, args.output.as_deref(), &options)
+        }),
+        Command::Generate(args) => run_generate(
+            args.width,
+            args.height,
+            args.border_colours,
+            args.interior_colours,
+            args.seed.unwrap_or_else(rand::random),
+            args.output.as_deref(),
+            args.solution.as_deref(),
+        ),
+        Command::Work(args) => {
+            let name = args.name.unwrap_or_else(|| {
+                let host = env::var("HOSTNAME").unwrap_or("worker".to_string());
+                format!("{host}-{}", process::id())
+            });
+            let threads = args.threads.unwrap_or_else(get_num_cores);
+            work(&args.coordinator, name, threads, args.progress_interval)
+                .map_err(|e| format!("Failed to work for {}: {e}", args.coordinator))
         }
+    };
+    if let Err(e) = result {
+        error!("{e}");
+        process::exit(1)
+    }
+}
 
-        info!("Result");
-
-        // This will only print valid numbers if you let the solver count how far you are.
-        let index_counts_clone = index_counts.clone();
-        let index_counts_locked = index_counts_clone.lock().unwrap();
-        for ii in 0..=256 {
-            let i: usize = ii as usize;
-            if index_counts_locked[i] != 0 {
-                println!("{i} {}", index_counts_locked[i].separate_with_commas());
-            }
-            total_index_count += index_counts_locked[i];
-        }
-        let elapsed_time_seconds = overall_stopwatch.elapsed().as_secs();
-        let rate = total_index_count / elapsed_time_seconds;
-        info!(
-            "Total {} nodes in {} seconds, {} per second, max depth {}",
-            total_index_count.separate_with_commas(),
-            elapsed_time_seconds.separate_with_commas(),
-            rate.separate_with_commas(),
-            *max_depth.lock().unwrap()
-        );
+/// Resolves the settings, exiting if they do not resolve. Only the commands that save results
+/// `record` the settings in the output directory; the others just name them.
+fn resolve_settings(args: SettingsArgs, defaults: RunDefaults, record: bool) -> Settings {
+    let mut settings = args.resolve(defaults).unwrap_or_else(|e| {
+        error!("{e}");
+        process::exit(1)
+    });
+    if record {
+        record_settings(&mut settings);
+    } else {
+        name_settings(&mut settings);
     }
+    settings
 }
@@ -0,0 +1,251 @@
+use crate::progress::{Progress, ProgressSnapshot};
+use log::warn;
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Serves the run's counters at `http://<addr>/metrics` in the Prometheus text format, from a
+/// thread of its own that runs until the process exits. Returns the address it is listening on,
+/// which gives the port when `addr` asks for port 0.
+pub fn serve_metrics(addr: &str, progress: Arc<Progress>) -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind(addr)?;
+    let local_addr = listener.local_addr()?;
+    let started = Instant::now();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let result = stream.and_then(|x| answer(x, &progress, started));
+            if let Err(e) = result {
+                warn!("Failed to answer a metrics request: {e}");
+            }
+        }
+    });
+    Ok(local_addr)
+}
+
+fn answer(mut stream: TcpStream, progress: &Progress, started: Instant) -> io::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // The headers say nothing the response depends on.
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => (
+            "200 OK",
+            format_metrics(&progress.snapshot(), started.elapsed()),
+        ),
+        _ => (
+            "404 Not Found",
+            "Metrics are served at /metrics\n".to_string(),
+        ),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    stream.flush()
+}
+
+/// Writes a snapshot of the counters in the Prometheus text format. `elapsed` is how long the
+/// counters have been running, from which the average nodes per second is worked out.
+pub fn format_metrics(snapshot: &ProgressSnapshot, elapsed: Duration) -> String {
+    let nodes = snapshot.nodes();
+    let workers = &snapshot.workers;
+    let mut text = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, samples: Vec<(String, String)>| {
+        let _ = writeln!(text, "# HELP {name} {help}\n# TYPE {name} {kind}");
+        for (labels, value) in samples {
+            let _ = writeln!(text, "{name}{labels} {value}");
+        }
+    };
+    let per_worker = |value: &dyn Fn(usize) -> String| {
+        (0..workers.len())
+            .map(|x| (format!("{{worker=\"{x}\"}}"), value(x)))
+            .collect()
+    };
+
+    metric(
+        "eternity_nodes_total",
+        "counter",
+        "Nodes visited by every worker.",
+        vec![(String::new(), nodes.to_string())],
+    );
+    metric(
+        "eternity_nodes_per_second",
+        "gauge",
+        "Nodes visited per second, averaged since the process started.",
+        vec![(
+            String::new(),
+            format!("{:.0}", nodes as f64 / elapsed.as_secs_f64().max(1.0)),
+        )],
+    );
+    metric(
+        "eternity_best_depth",
+        "gauge",
+        "The most pieces any worker has placed.",
+        vec![(
+            String::new(),
+            workers
+                .iter()
+                .map(|x| x.best_depth)
+                .max()
+                .unwrap_or(0)
+                .to_string(),
+        )],
+    );
+    metric(
+        "eternity_boards_saved_total",
+        "counter",
+        "Boards sent to the sinks without an error.",
+        vec![(
+            String::new(),
+            workers
+                .iter()
+                .map(|x| x.boards_saved)
+                .sum::<u64>()
+                .to_string(),
+        )],
+    );
+    metric(
+        "eternity_depth_nodes_total",
+        "counter",
+        "Nodes visited at each depth, the solve_index_counts histogram.",
+        snapshot
+            .solve_index_counts
+            .iter()
+            .enumerate()
+            .filter(|x| *x.1 != 0)
+            .map(|(depth, count)| (format!("{{depth=\"{depth}\"}}"), count.to_string()))
+            .collect(),
+    );
+    metric(
+        "eternity_worker_nodes_total",
+        "counter",
+        "Nodes visited by each worker.",
+        per_worker(&|x| workers[x].nodes.to_string()),
+    );
+    metric(
+        "eternity_worker_depth",
+        "gauge",
+        "Pieces each worker had placed when it last published its counters.",
+        per_worker(&|x| workers[x].depth.to_string()),
+    );
+    metric(
+        "eternity_worker_best_depth",
+        "gauge",
+        "The most pieces each worker has placed.",
+        per_worker(&|x| workers[x].best_depth.to_string()),
+    );
+    metric(
+        "eternity_worker_restarts_total",
+        "counter",
+        "Searches each worker has started after its first.",
+        per_worker(&|x| workers[x].searches.saturating_sub(1).to_string()),
+    );
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::progress::WorkerSnapshot;
+    use std::io::Read;
+
+    fn worker(nodes: u64, depth: usize, best_depth: usize, searches: u64) -> WorkerSnapshot {
+        WorkerSnapshot {
+            nodes,
+            depth,
+            best_depth,
+            searches,
+            boards_saved: searches,
+        }
+    }
+
+    #[test]
+    fn counters_are_written_in_the_text_format() {
+        let snapshot = ProgressSnapshot {
+            workers: vec![worker(10, 2, 3, 1), worker(20, 4, 4, 3)],
+            solve_index_counts: vec![0, 12, 0, 18],
+        };
+        let text = format_metrics(&snapshot, Duration::from_secs(3));
+        let expected = r#"# HELP eternity_nodes_total Nodes visited by every worker.
+# TYPE eternity_nodes_total counter
+eternity_nodes_total 30
+# HELP eternity_nodes_per_second Nodes visited per second, averaged since the process started.
+# TYPE eternity_nodes_per_second gauge
+eternity_nodes_per_second 10
+# HELP eternity_best_depth The most pieces any worker has placed.
+# TYPE eternity_best_depth gauge
+eternity_best_depth 4
+# HELP eternity_boards_saved_total Boards sent to the sinks without an error.
+# TYPE eternity_boards_saved_total counter
+eternity_boards_saved_total 4
+# HELP eternity_depth_nodes_total Nodes visited at each depth, the solve_index_counts histogram.
+# TYPE eternity_depth_nodes_total counter
+eternity_depth_nodes_total{depth="1"} 12
+eternity_depth_nodes_total{depth="3"} 18
+# HELP eternity_worker_nodes_total Nodes visited by each worker.
+# TYPE eternity_worker_nodes_total counter
+eternity_worker_nodes_total{worker="0"} 10
+eternity_worker_nodes_total{worker="1"} 20
+# HELP eternity_worker_depth Pieces each worker had placed when it last published its counters.
+# TYPE eternity_worker_depth gauge
+eternity_worker_depth{worker="0"} 2
+eternity_worker_depth{worker="1"} 4
+# HELP eternity_worker_best_depth The most pieces each worker has placed.
+# TYPE eternity_worker_best_depth gauge
+eternity_worker_best_depth{worker="0"} 3
+eternity_worker_best_depth{worker="1"} 4
+# HELP eternity_worker_restarts_total Searches each worker has started after its first.
+# TYPE eternity_worker_restarts_total counter
+eternity_worker_restarts_total{worker="0"} 0
+eternity_worker_restarts_total{worker="1"} 2
+"#;
+        assert_eq!(text, expected);
+    }
+
+    #[test]
+    fn rates_in_the_first_second_are_not_inflated() {
+        let snapshot = ProgressSnapshot {
+            workers: vec![],
+            solve_index_counts: vec![500],
+        };
+        let text = format_metrics(&snapshot, Duration::from_millis(10));
+        assert!(text.contains("\neternity_nodes_per_second 500\n"), "{text}");
+        assert!(text.contains("\neternity_best_depth 0\n"), "{text}");
+    }
+
+    #[test]
+    fn metrics_are_served_over_http() {
+        let progress = Arc::new(Progress::new(2, 16));
+        let addr = serve_metrics("127.0.0.1:0", progress).unwrap();
+        let get = |path: &str| {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            write!(stream, "GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+
+        let response = get("/metrics");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{response}");
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        assert!(head.contains(&format!("Content-Length: {}", body.len())));
+        assert!(body.contains("eternity_worker_nodes_total{worker=\"1\"} 0\n"));
+
+        assert!(get("/").starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+}
@@ -0,0 +1,119 @@
+use crate::structs::Piece;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Reads a piece file: one `top right bottom left` line per piece, with piece IDs assigned from 1
+/// in file order. Blank lines and lines starting with `#` are ignored.
+pub fn load_pieces(path: &Path) -> io::Result<Vec<Piece>> {
+    parse_pieces(&fs::read_to_string(path)?)
+}
+
+pub fn parse_pieces(text: &str) -> io::Result<Vec<Piece>> {
+    let mut pieces = Vec::new();
+
+    for (line_number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let sides: Vec<u8> = line
+            .split_whitespace()
+            .map(|side| side.parse::<u8>())
+            .collect::<Result<_, _>>()
+            .map_err(|e| invalid_data(format!("line {}: {e}", line_number + 1)))?;
+        if sides.len() != 4 {
+            return Err(invalid_data(format!(
+                "line {}: expected 4 sides, found {}",
+                line_number + 1,
+                sides.len()
+            )));
+        }
+
+        let piece = Piece {
+            reid: (pieces.len() + 1) as u16,
+            top: sides[0],
+            right: sides[1],
+            bottom: sides[2],
+            left: sides[3],
+        };
+        let piece = normalise_orientation(piece)
+            .map_err(|e| invalid_data(format!("line {}: {e}", line_number + 1)))?;
+        pieces.push(piece);
+    }
+
+    if pieces.is_empty() {
+        return Err(invalid_data("no pieces found".to_string()));
+    }
+    Ok(pieces)
+}
+
+/// Writes pieces in the format `load_pieces` reads, one `top right bottom left` line per piece
+/// in ID order.
+pub fn format_pieces(pieces: &[Piece]) -> String {
+    let mut sorted: Vec<&Piece> = pieces.iter().collect();
+    sorted.sort_by_key(|x| x.reid);
+    sorted
+        .iter()
+        .map(|x| format!("{} {} {} {}\n", x.top, x.right, x.bottom, x.left))
+        .collect()
+}
+
+/// Turns corners and sides so that their grey edges face down (and left, for corners), which is
+/// the orientation the solver expects rotation 0 to have. Pieces that cannot be turned that way,
+/// a corner whose grey edges are opposite each other or a piece with more than two, are an error.
+pub(crate) fn normalise_orientation(piece: Piece) -> Result<Piece, String> {
+    let reid = piece.reid;
+    if piece.piece_type() > 2 {
+        return Err(format!(
+            "piece {reid} has {} grey edges",
+            piece.piece_type()
+        ));
+    }
+    (0..4)
+        .map(|rotations| piece.rotated(rotations))
+        .find(|p| match piece.piece_type() {
+            2 => p.bottom == 0 && p.left == 0,
+            1 => p.bottom == 0,
+            _ => true,
+        })
+        .ok_or_else(|| format!("piece {reid} has grey edges on opposite sides"))
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn corners_and_sides_are_turned_grey_edges_down() {
+        let pieces = parse_pieces("0 0 1 2\n# a side\n3 0 4 5\n1 2 3 4\n").unwrap();
+        let sides: Vec<_> = pieces
+            .iter()
+            .map(|x| (x.reid, x.top, x.right, x.bottom, x.left))
+            .collect();
+        assert_eq!(sides, [(1, 1, 2, 0, 0), (2, 5, 3, 0, 4), (3, 1, 2, 3, 4)]);
+        assert_eq!(parse_pieces(&format_pieces(&pieces)).unwrap(), pieces);
+    }
+
+    #[test]
+    fn corners_with_opposite_grey_edges_are_rejected() {
+        let error = parse_pieces("1 2 3 4\n0 5 0 6\n").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "line 2: piece 2 has grey edges on opposite sides"
+        );
+        assert!(parse_pieces("0 0 0 1\n").is_err());
+    }
+
+    #[test]
+    fn lines_need_four_sides() {
+        assert!(parse_pieces("1 2 3\n").is_err());
+        assert!(parse_pieces("1 2 3 x\n").is_err());
+        assert!(parse_pieces("# nothing\n").is_err());
+    }
+}
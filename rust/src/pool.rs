@@ -0,0 +1,348 @@
+use crate::checkpoint::{Checkpoint, JobCheckpoint, LoopCheckpoint};
+use crate::config::SolverConfig;
+use crate::progress::Progress;
+use crate::puzzle::Puzzle;
+use crate::run::STOP;
+use crate::sinks::ResultSink;
+use crate::solve_puzzle::{solve_puzzle, SearchState};
+use crate::solver_data::{prepare_pieces_and_heuristics, SolverData};
+use crate::structs::SolverResult;
+use crate::util::derive_seed;
+use log::{info, warn};
+use std::collections::VecDeque;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How many searches each slot runs in a loop, each from a fresh seed.
+pub const REPEATS: u32 = 5;
+/// How often a worker waiting for the feeder checks for jobs handed back by other workers.
+const RETURNED_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// One search: a repeat of one of a loop's slots, resumed from `state` if it was checkpointed.
+pub struct Job {
+    pub loop_count: u64,
+    pub slots: usize,
+    pub slot: usize,
+    pub repeat: u32,
+    pub seed: u64,
+    pub solver_data: Arc<SolverData>,
+    pub state: Option<SearchState>,
+}
+
+/// The jobs waiting for a worker: those handed back by workers that went away, then those from
+/// the feeder.
+pub struct JobQueue {
+    returned: Mutex<VecDeque<Job>>,
+    jobs: Mutex<Receiver<Job>>,
+}
+
+impl JobQueue {
+    pub fn new(jobs: Receiver<Job>) -> JobQueue {
+        JobQueue {
+            returned: Mutex::new(VecDeque::new()),
+            jobs: Mutex::new(jobs),
+        }
+    }
+
+    /// Waits for the next job. Returns `None` once the feeder has stopped and every job handed
+    /// back has been taken.
+    pub fn next(&self) -> Option<Job> {
+        loop {
+            if let Some(job) = self.returned.lock().unwrap().pop_front() {
+                return Some(job);
+            }
+            // Only waits a while for the feeder, so that a job handed back meanwhile is not
+            // left until the feeder sends another.
+            match self
+                .jobs
+                .lock()
+                .unwrap()
+                .recv_timeout(RETURNED_POLL_INTERVAL)
+            {
+                Ok(job) => return Some(job),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => {
+                    return self.returned.lock().unwrap().pop_front()
+                }
+            }
+        }
+    }
+
+    /// Hands back a job that was taken but not run, for the next worker to take.
+    pub fn give_back(&self, job: Job) {
+        self.returned.lock().unwrap().push_back(job);
+    }
+}
+
+/// What a worker sends back when a search ends, whether it finished or was stopped.
+pub struct JobResult {
+    pub loop_count: u64,
+    pub slots: usize,
+    pub slot: usize,
+    pub repeat: u32,
+    pub worker: usize,
+    pub solver_result: SolverResult,
+    pub seconds: u64,
+}
+
+/// A loop that was started by an earlier run and is picked up again.
+pub struct ResumedLoop {
+    pub loop_count: u64,
+    pub slots: usize,
+    pub solver_data: Arc<SolverData>,
+    /// The `(slot, repeat)` of each search that has already finished.
+    pub finished: Vec<(usize, u32)>,
+}
+
+/// What every worker shares.
+#[derive(Clone)]
+pub struct WorkerContext {
+    pub max_node_count: u64,
+    pub sinks: Arc<Vec<Box<dyn ResultSink>>>,
+    pub progress: Arc<Progress>,
+    pub checkpoint: Option<Arc<Checkpoint>>,
+}
+
+/// Starts `threads` workers, each taking the next job from `jobs` as soon as it is free and
+/// sending back the result, until the jobs run out or the run is stopped.
+pub fn spawn_workers(
+    threads: usize,
+    jobs: Arc<JobQueue>,
+    results: Sender<JobResult>,
+    context: WorkerContext,
+) {
+    for worker in 0..threads {
+        let jobs = Arc::clone(&jobs);
+        let results = results.clone();
+        let context = context.clone();
+        std::thread::spawn(move || {
+            while !STOP.load(Ordering::Relaxed) {
+                let Some(job) = jobs.next() else {
+                    break;
+                };
+                let result = run_job(job, worker, &context);
+                if results.send(result).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+}
+
+fn run_job(job: Job, worker: usize, context: &WorkerContext) -> JobResult {
+    let Job {
+        loop_count,
+        slots,
+        slot,
+        repeat,
+        seed,
+        solver_data,
+        state,
+    } = job;
+    info!("Core {worker:02}: start loop {loop_count}, slot {slot:02}, repeat {repeat}");
+    let stopwatch = Instant::now();
+    let mut state = state.unwrap_or_else(|| SearchState::new(&solver_data, worker, seed));
+    state.worker = worker;
+
+    let save_state = |state: SearchState| {
+        let job = JobCheckpoint {
+            loop_count,
+            slot,
+            repeat,
+            state,
+        };
+        if let Some(checkpoint) = &context.checkpoint {
+            if let Err(e) = checkpoint.save_job(&job) {
+                warn!("Core {worker:02}: failed to write checkpoint: {e}");
+            }
+        }
+    };
+    let solver_result = solve_puzzle(
+        &solver_data,
+        state,
+        context.max_node_count,
+        Some(&context.sinks),
+        Some(&context.progress.worker(worker)),
+        Some(&STOP),
+        context
+            .checkpoint
+            .as_ref()
+            .map(|_| &save_state as &dyn Fn(SearchState)),
+    );
+
+    JobResult {
+        loop_count,
+        slots,
+        slot,
+        repeat,
+        worker,
+        solver_result,
+        seconds: stopwatch.elapsed().as_secs(),
+    }
+}
+
+/// Where the feeder finds the loops it hands out.
+pub struct FeederPlan {
+    pub puzzle: Puzzle,
+    pub config: SolverConfig,
+    pub master_seed: u64,
+    pub slots: usize,
+    pub resumed: Vec<ResumedLoop>,
+    /// The first loop to build after the resumed ones.
+    pub next_loop: u64,
+    pub checkpoint: Option<Arc<Checkpoint>>,
+}
+
+/// Hands out the searches of the resumed loops and then of new loops, one at a time to
+/// whichever worker asks first. The next loop's solver data is built in the background while
+/// the current loop's searches are handed out, so that no worker waits between loops.
+pub fn spawn_feeder(plan: FeederPlan, jobs: SyncSender<Job>) {
+    std::thread::spawn(move || {
+        // Builds one loop ahead, waiting until the feeder takes it before starting the next.
+        let (built_sender, built) = mpsc::sync_channel(0);
+        let (puzzle, config) = (plan.puzzle.clone(), plan.config.clone());
+        let (master_seed, next_loop) = (plan.master_seed, plan.next_loop);
+        std::thread::spawn(move || {
+            for loop_count in next_loop.. {
+                let seed = derive_seed(master_seed, &[loop_count]);
+                let solver_data = prepare_pieces_and_heuristics(&puzzle, &config, seed);
+                if built_sender
+                    .send((loop_count, Arc::new(solver_data)))
+                    .is_err()
+                {
+                    return;
+                }
+            }
+        });
+
+        for resumed in &plan.resumed {
+            let more = feed_loop(
+                &plan,
+                resumed.loop_count,
+                resumed.slots,
+                &resumed.solver_data,
+                &resumed.finished,
+                &jobs,
+            );
+            if !more {
+                return;
+            }
+        }
+
+        for (loop_count, solver_data) in built {
+            if STOP.load(Ordering::Relaxed) {
+                return;
+            }
+            if let Some(checkpoint) = &plan.checkpoint {
+                let num_cells = plan.puzzle.num_cells();
+                let result = checkpoint
+                    .save_solver_data(loop_count, &solver_data)
+                    .and_then(|_| {
+                        checkpoint.save_loop(&LoopCheckpoint {
+                            loop_count,
+                            slots: plan.slots,
+                            finished: vec![],
+                            index_counts: vec![0; num_cells + 1],
+                            max_depth: 0,
+                        })
+                    });
+                if let Err(e) = result {
+                    warn!("Failed to write checkpoint for loop {loop_count}: {e}");
+                }
+            }
+            info!("Starting loop {loop_count}");
+            if !feed_loop(&plan, loop_count, plan.slots, &solver_data, &[], &jobs) {
+                return;
+            }
+        }
+    });
+}
+
+/// Hands out every search of a loop that has not already finished, repeat by repeat. Returns
+/// false once the run is stopped or the workers have gone.
+fn feed_loop(
+    plan: &FeederPlan,
+    loop_count: u64,
+    slots: usize,
+    solver_data: &Arc<SolverData>,
+    finished: &[(usize, u32)],
+    jobs: &SyncSender<Job>,
+) -> bool {
+    for repeat in 1..=REPEATS {
+        for slot in 0..slots {
+            if finished.contains(&(slot, repeat)) {
+                continue;
+            }
+            if STOP.load(Ordering::Relaxed) {
+                return false;
+            }
+            let state = plan.checkpoint.as_ref().and_then(|x| {
+                match x.load_job(loop_count, slot, repeat) {
+                    Ok(job) => job.map(|x| x.state),
+                    Err(e) => {
+                        warn!("Failed to read checkpoint for loop {loop_count}, slot {slot:02}, repeat {repeat}: {e}");
+                        None
+                    }
+                }
+            });
+            let job = Job {
+                loop_count,
+                slots,
+                slot,
+                repeat,
+                seed: derive_seed(plan.master_seed, &[loop_count, slot as u64, repeat as u64]),
+                solver_data: Arc::clone(solver_data),
+                state,
+            };
+            if jobs.send(job).is_err() {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generate::generate_puzzle;
+
+    fn job(slot: usize) -> Job {
+        let puzzle = generate_puzzle(4, 4, 3, 4, 2).unwrap().puzzle;
+        let config = SolverConfig::for_board(4, 4);
+        Job {
+            loop_count: 1,
+            slots: 2,
+            slot,
+            repeat: 1,
+            seed: 0,
+            solver_data: Arc::new(prepare_pieces_and_heuristics(&puzzle, &config, 1)),
+            state: None,
+        }
+    }
+
+    #[test]
+    fn jobs_handed_back_are_taken_while_the_feeder_is_quiet() {
+        let (feeder, jobs) = mpsc::sync_channel(0);
+        let queue = Arc::new(JobQueue::new(jobs));
+        let waiting = {
+            let queue = Arc::clone(&queue);
+            std::thread::spawn(move || queue.next().map(|x| x.slot))
+        };
+        std::thread::sleep(Duration::from_millis(50));
+        let stopwatch = Instant::now();
+        queue.give_back(job(1));
+        assert_eq!(waiting.join().unwrap(), Some(1));
+        assert!(stopwatch.elapsed() < 4 * RETURNED_POLL_INTERVAL);
+
+        // Jobs handed back come first, then the feeder's, and nothing once it has stopped.
+        queue.give_back(job(0));
+        let sender = std::thread::spawn(move || feeder.send(job(1)).unwrap());
+        assert_eq!(queue.next().map(|x| x.slot), Some(0));
+        assert_eq!(queue.next().map(|x| x.slot), Some(1));
+        sender.join().unwrap();
+        assert!(queue.next().is_none());
+    }
+}
@@ -0,0 +1,52 @@
+use crate::solve_puzzle::SearchState;
+use crate::solver_data::SolverData;
+use crate::structs::RotatedPiece;
+
+/// Lists every way the search can make its first `depth` placements, in the order it tries them.
+/// Each prefix holds its pieces in search order, ready for `SearchState::from_prefix`. The break
+/// and heuristic limits apply just as they do in the search, so the searches below the prefixes
+/// together cover everything a search from the first corner can reach. `depth` must be at least
+/// 1, for the first corner.
+pub fn enumerate_prefixes(solver_data: &SolverData, depth: usize) -> Vec<Vec<RotatedPiece>> {
+    let depth = depth.min(solver_data.puzzle.num_cells());
+    let mut prefixes = vec![];
+    let mut state = SearchState::from_prefix(solver_data, 0, &[]);
+    extend(solver_data, &mut state, depth, &mut prefixes);
+    prefixes
+}
+
+fn extend(
+    solver_data: &SolverData,
+    state: &mut SearchState,
+    depth: usize,
+    prefixes: &mut Vec<Vec<RotatedPiece>>,
+) {
+    if state.solve_index == depth {
+        let width = solver_data.puzzle.width;
+        let prefix = solver_data.board_search_sequence[..depth]
+            .iter()
+            .map(|x| state.board[x.row as usize * width + x.column as usize])
+            .collect();
+        prefixes.push(prefix);
+        return;
+    }
+    for piece in state.candidates(solver_data) {
+        state.place(solver_data, piece);
+        extend(solver_data, state, depth, prefixes);
+        state.take_back(solver_data);
+    }
+}
+
+/// A hash of the pieces and rotations of every prefix, in order, which changes if the pieces or
+/// the settings that shape the search do.
+pub fn prefixes_hash(prefixes: &[Vec<RotatedPiece>]) -> String {
+    let mut context = md5::Context::new();
+    for prefix in prefixes {
+        for piece in prefix {
+            context.consume(piece.reid.to_le_bytes());
+            context.consume([piece.rotations]);
+        }
+        context.consume([0xff]);
+    }
+    format!("{:x}", context.finalize())
+}
@@ -0,0 +1,170 @@
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+
+/// Counters a worker publishes while it searches, for another thread to read. The search only
+/// writes them every `PROGRESS_NODE_INTERVAL` nodes, so they lag slightly behind.
+pub struct WorkerProgress {
+    depth: AtomicUsize,
+    best_depth: AtomicUsize,
+    searches: AtomicU64,
+    boards_saved: AtomicU64,
+    solve_index_counts: Vec<AtomicU64>,
+}
+
+/// A copy of one worker's counters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkerSnapshot {
+    pub nodes: u64,
+    /// How many pieces the worker had placed when it last published.
+    pub depth: usize,
+    /// The deepest the worker has been in any search.
+    pub best_depth: usize,
+    /// How many searches the worker has started, counting resumed ones.
+    pub searches: u64,
+    /// How many boards the worker has sent to its sink without an error.
+    pub boards_saved: u64,
+}
+
+impl WorkerProgress {
+    pub fn new(num_cells: usize) -> WorkerProgress {
+        WorkerProgress {
+            depth: AtomicUsize::new(0),
+            best_depth: AtomicUsize::new(0),
+            searches: AtomicU64::new(0),
+            boards_saved: AtomicU64::new(0),
+            solve_index_counts: (0..=num_cells).map(|_| AtomicU64::new(0)).collect(),
+        }
+    }
+
+    /// Adds the nodes counted since the last call, as the difference between
+    /// `solve_index_counts` and `published`, which is then brought up to date. Counts below
+    /// those published, such as from a remote worker that has started again, add nothing.
+    pub fn publish(
+        &self,
+        depth: usize,
+        best_depth: usize,
+        solve_index_counts: &[u64],
+        published: &mut [u64],
+    ) {
+        for (i, (count, published)) in solve_index_counts.iter().zip(published).enumerate() {
+            if *count != *published {
+                self.solve_index_counts[i]
+                    .fetch_add(count.saturating_sub(*published), Ordering::Relaxed);
+                *published = *count;
+            }
+        }
+        self.depth.store(depth, Ordering::Relaxed);
+        self.best_depth.fetch_max(best_depth, Ordering::Relaxed);
+    }
+
+    pub fn record_search(&self) {
+        self.searches.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_board_saved(&self) {
+        self.boards_saved.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// The nodes the worker has visited at each depth.
+    pub fn solve_index_counts(&self) -> Vec<u64> {
+        self.solve_index_counts
+            .iter()
+            .map(|x| x.load(Ordering::Relaxed))
+            .collect()
+    }
+
+    pub fn snapshot(&self) -> WorkerSnapshot {
+        WorkerSnapshot {
+            nodes: self
+                .solve_index_counts
+                .iter()
+                .map(|x| x.load(Ordering::Relaxed))
+                .sum(),
+            depth: self.depth.load(Ordering::Relaxed),
+            best_depth: self.best_depth.load(Ordering::Relaxed),
+            searches: self.searches.load(Ordering::Relaxed),
+            boards_saved: self.boards_saved.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// The counters of every worker in a run. They cover the work done since the process started,
+/// not any carried over from a checkpoint. Workers can join while the run goes on, such as the
+/// threads of a remote worker process.
+pub struct Progress {
+    workers: RwLock<Vec<Arc<WorkerProgress>>>,
+    num_cells: usize,
+}
+
+/// A copy of every worker's counters and the histogram of nodes by depth across all of them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProgressSnapshot {
+    pub workers: Vec<WorkerSnapshot>,
+    pub solve_index_counts: Vec<u64>,
+}
+
+impl Progress {
+    pub fn new(num_workers: usize, num_cells: usize) -> Progress {
+        Progress {
+            workers: RwLock::new(
+                (0..num_workers)
+                    .map(|_| Arc::new(WorkerProgress::new(num_cells)))
+                    .collect(),
+            ),
+            num_cells,
+        }
+    }
+
+    pub fn worker(&self, index: usize) -> Arc<WorkerProgress> {
+        Arc::clone(&self.workers.read().unwrap()[index])
+    }
+
+    /// Adds counters for `count` more workers and returns the index of the first.
+    pub fn add_workers(&self, count: usize) -> usize {
+        let mut workers = self.workers.write().unwrap();
+        let first = workers.len();
+        workers.extend((0..count).map(|_| Arc::new(WorkerProgress::new(self.num_cells))));
+        first
+    }
+
+    pub fn snapshot(&self) -> ProgressSnapshot {
+        let workers = self.workers.read().unwrap();
+        let mut solve_index_counts = vec![0; self.num_cells + 1];
+        for worker in workers.iter() {
+            for (total, count) in solve_index_counts
+                .iter_mut()
+                .zip(&worker.solve_index_counts)
+            {
+                *total += count.load(Ordering::Relaxed);
+            }
+        }
+        ProgressSnapshot {
+            workers: workers.iter().map(|x| x.snapshot()).collect(),
+            solve_index_counts,
+        }
+    }
+}
+
+impl ProgressSnapshot {
+    pub fn nodes(&self) -> u64 {
+        self.solve_index_counts.iter().sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_that_go_back_add_nothing() {
+        let worker = WorkerProgress::new(2);
+        let mut published = vec![0; 3];
+        worker.publish(2, 2, &[1, 5, 3], &mut published);
+        // Counts that start again from lower ones are taken from there.
+        worker.publish(1, 1, &[1, 2, 0], &mut published);
+        assert_eq!(worker.solve_index_counts(), [1, 5, 3]);
+        worker.publish(2, 2, &[1, 4, 1], &mut published);
+        assert_eq!(worker.solve_index_counts(), [1, 7, 4]);
+        assert_eq!(published, [1, 4, 1]);
+    }
+}
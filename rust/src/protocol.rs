@@ -0,0 +1,199 @@
+use crate::config::SolverConfig;
+use crate::puzzle::Puzzle;
+use crate::solve_puzzle::SearchState;
+use crate::structs::{RotatedPiece, SolverResult};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::io::{self, BufRead, Write};
+
+/// A search for a worker process to run: the seed of the solver data, the seed of the search
+/// and its node budget, or the state to carry on from.
+#[derive(Serialize, Deserialize)]
+pub struct WorkUnit {
+    pub id: u64,
+    pub solver_data_seed: u64,
+    pub search_seed: u64,
+    pub max_node_count: u64,
+    pub state: Option<SearchState>,
+}
+
+/// One thread's counters, as a worker process reports them.
+#[derive(Serialize, Deserialize)]
+pub struct ThreadProgress {
+    pub depth: usize,
+    pub best_depth: usize,
+    /// Nodes visited at each depth since the worker process started.
+    pub solve_index_counts: Vec<u64>,
+}
+
+/// What the coordinator sends a worker process.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ToWorker {
+    /// Sent once, first: the puzzle and the solver config every unit uses.
+    Setup {
+        puzzle: Puzzle,
+        config: SolverConfig,
+    },
+    Unit(WorkUnit),
+    /// Stop every search, report them and disconnect.
+    Stop,
+}
+
+/// What a worker process sends the coordinator. Threads are numbered from 0 within the process.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ToCoordinator {
+    /// Sent once, first.
+    Hello {
+        name: String,
+        threads: usize,
+    },
+    /// A thread is free for another unit.
+    Ready,
+    /// A board at least `min_solve_index_to_save` deep.
+    Board {
+        unit: u64,
+        thread: usize,
+        board: Vec<RotatedPiece>,
+    },
+    Progress {
+        threads: Vec<ThreadProgress>,
+    },
+    /// Where a unit's search has got to, sent as often as a local search checkpoints, so that
+    /// the coordinator can checkpoint it or hand it to another worker from there.
+    State {
+        unit: u64,
+        thread: usize,
+        state: SearchState,
+    },
+    /// A unit has ended, either finished or stopped.
+    Done {
+        unit: u64,
+        thread: usize,
+        result: SolverResult,
+    },
+}
+
+/// Messages are single lines of JSON.
+pub fn write_message<T: Serialize>(writer: &mut impl Write, message: &T) -> io::Result<()> {
+    let line = serde_json::to_string(message)? + "\n";
+    writer.write_all(line.as_bytes())?;
+    writer.flush()
+}
+
+/// Reads the next message, or returns `None` when the other side has closed the connection.
+pub fn read_message<T: DeserializeOwned>(reader: &mut impl BufRead) -> io::Result<Option<T>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::from_str(&line)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SolverConfig;
+    use crate::generate::generate_puzzle;
+    use crate::solve_puzzle::solve_puzzle;
+    use crate::solver_data::prepare_pieces_and_heuristics;
+    use std::io::Cursor;
+
+    /// Messages have no `PartialEq`, so they are compared by their JSON.
+    fn json<T: Serialize>(message: &T) -> serde_json::Value {
+        serde_json::to_value(message).unwrap()
+    }
+
+    /// Writes every message to one stream and reads them back in order.
+    fn round_trip<T: Serialize + DeserializeOwned>(messages: &[T]) {
+        let mut stream = vec![];
+        for message in messages {
+            write_message(&mut stream, message).unwrap();
+        }
+        let text = String::from_utf8(stream.clone()).unwrap();
+        assert_eq!(text.lines().count(), messages.len());
+
+        let mut reader = Cursor::new(stream);
+        for message in messages {
+            let read: T = read_message(&mut reader).unwrap().unwrap();
+            assert_eq!(json(&read), json(message));
+        }
+        assert!(read_message::<T>(&mut reader).unwrap().is_none());
+    }
+
+    #[test]
+    fn messages_to_workers_read_back_as_written() {
+        let puzzle = generate_puzzle(4, 4, 3, 4, 2).unwrap().puzzle;
+        let config = SolverConfig::for_board(4, 4);
+        let solver_data = prepare_pieces_and_heuristics(&puzzle, &config, 1);
+        let unit = |id, state| {
+            ToWorker::Unit(WorkUnit {
+                id,
+                solver_data_seed: 1,
+                search_seed: 2,
+                max_node_count: 3,
+                state,
+            })
+        };
+        round_trip(&[
+            ToWorker::Setup { puzzle, config },
+            unit(1, None),
+            unit(2, Some(SearchState::new(&solver_data, 1, 5))),
+            ToWorker::Stop,
+        ]);
+    }
+
+    #[test]
+    fn messages_to_the_coordinator_read_back_as_written() {
+        let generated = generate_puzzle(4, 4, 3, 4, 2).unwrap();
+        let config = SolverConfig::for_board(4, 4);
+        let solver_data = prepare_pieces_and_heuristics(&generated.puzzle, &config, 1);
+        let state = SearchState::new(&solver_data, 0, 5);
+        let result = solve_puzzle(&solver_data, state.clone(), 1_000, None, None, None, None);
+        round_trip(&[
+            ToCoordinator::Hello {
+                name: "host-1".to_string(),
+                threads: 2,
+            },
+            ToCoordinator::Ready,
+            ToCoordinator::Board {
+                unit: 1,
+                thread: 1,
+                board: generated.solution,
+            },
+            ToCoordinator::Progress {
+                threads: vec![ThreadProgress {
+                    depth: 3,
+                    best_depth: 7,
+                    solve_index_counts: vec![0, 1, 2],
+                }],
+            },
+            ToCoordinator::State {
+                unit: 1,
+                thread: 0,
+                state,
+            },
+            ToCoordinator::Done {
+                unit: 1,
+                thread: 0,
+                result,
+            },
+        ]);
+    }
+
+    #[test]
+    fn messages_are_tagged_by_type() {
+        let mut stream = vec![];
+        write_message(&mut stream, &ToCoordinator::Ready).unwrap();
+        assert_eq!(stream, b"{\"type\":\"ready\"}\n");
+
+        let mut reader = Cursor::new(b"{\"type\":\"unknown\"}\n".to_vec());
+        assert!(read_message::<ToCoordinator>(&mut reader).is_err());
+        let mut reader = Cursor::new(b"{\"type\":\"stop\"}\n".to_vec());
+        assert!(matches!(
+            read_message::<ToWorker>(&mut reader).unwrap(),
+            Some(ToWorker::Stop)
+        ));
+    }
+}
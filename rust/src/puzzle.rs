@@ -0,0 +1,103 @@
+use crate::structs::{Piece, RotatedPiece};
+use serde::{Deserialize, Serialize};
+
+/// The Eternity II starter piece as it must be placed at row 7, column 7 (counted from the bottom
+/// left): piece 139 turned twice.
+const E2_START_PIECE: Piece = Piece {
+    reid: 139,
+    top: 18,
+    right: 6,
+    bottom: 6,
+    left: 11,
+};
+const E2_START_ROW: u8 = 7;
+const E2_START_COLUMN: u8 = 7;
+
+/// A piece that must sit at a fixed cell with a fixed rotation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Clue {
+    pub reid: u16,
+    pub row: u8,
+    pub column: u8,
+    pub rotations: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Puzzle {
+    pub width: usize,
+    pub height: usize,
+    pub num_colours: usize,
+    pub pieces: Vec<Piece>,
+    pub start_piece: Option<Clue>,
+}
+
+impl Puzzle {
+    /// Builds a puzzle from a piece set, working out the board size from the number of corner,
+    /// side and middle pieces. When the two sides differ the board is laid out wider than it is
+    /// tall. The Eternity II starter clue is applied when the set contains it on a 16x16 board.
+    pub fn from_pieces(pieces: Vec<Piece>) -> Result<Puzzle, String> {
+        let corners = pieces.iter().filter(|x| x.piece_type() == 2).count();
+        let sides = pieces.iter().filter(|x| x.piece_type() == 1).count();
+        if corners != 4 {
+            return Err(format!("expected 4 corner pieces, found {corners}"));
+        }
+        if sides % 2 != 0 {
+            return Err(format!("found an odd number of side pieces ({sides})"));
+        }
+
+        // width + height = sides / 2 + 4 and width * height = number of pieces.
+        let sum = sides / 2 + 4;
+        let count = pieces.len();
+        let (width, height) = (sum.div_ceil(2)..=sum)
+            .map(|width| (width, sum - width))
+            .find(|&(width, height)| width * height == count)
+            .ok_or_else(|| {
+                format!("{count} pieces with {sides} side pieces do not make a rectangular board")
+            })?;
+
+        let num_colours = pieces
+            .iter()
+            .flat_map(|x| [x.top, x.right, x.bottom, x.left])
+            .max()
+            .unwrap_or(0) as usize
+            + 1;
+
+        let start_piece = if width == 16 && height == 16 {
+            pieces
+                .iter()
+                .find(|x| x.reid == E2_START_PIECE.reid)
+                .and_then(|x| (0..4).find(|&r| x.rotated(r) == E2_START_PIECE))
+                .map(|rotations| Clue {
+                    reid: E2_START_PIECE.reid,
+                    row: E2_START_ROW,
+                    column: E2_START_COLUMN,
+                    rotations,
+                })
+        } else {
+            None
+        };
+
+        Ok(Puzzle {
+            width,
+            height,
+            num_colours,
+            pieces,
+            start_piece,
+        })
+    }
+
+    pub fn num_cells(&self) -> usize {
+        self.width * self.height
+    }
+
+    /// Returns the sides of a placed piece as they face on the board, or `None` for an empty cell.
+    pub fn placed_piece(&self, rotated_piece: &RotatedPiece) -> Option<Piece> {
+        if rotated_piece.reid == 0 {
+            return None;
+        }
+        self.pieces
+            .iter()
+            .find(|x| x.reid == rotated_piece.reid)
+            .map(|x| x.rotated(rotated_piece.rotations))
+    }
+}
@@ -0,0 +1,731 @@
+use crate::checkpoint::{Checkpoint, JobCheckpoint};
+use crate::config::SolverConfig;
+use crate::pool::{Job, JobQueue, JobResult};
+use crate::progress::Progress;
+use crate::protocol::{
+    read_message, write_message, ThreadProgress, ToCoordinator, ToWorker, WorkUnit,
+};
+use crate::puzzle::Puzzle;
+use crate::run::{spawn_reporter, STOP};
+use crate::sinks::{FoundBoard, ResultSink};
+use crate::solve_puzzle::{solve_puzzle, SearchState};
+use crate::solver_data::{prepare_pieces_and_heuristics, SolverData};
+use log::{error, info, warn};
+use std::collections::HashMap;
+use std::io::{self, BufReader};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+/// How often the listener checks whether the run has been stopped, and idle worker threads
+/// whether they should give up waiting for a unit.
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+/// How often a worker process sends its counters to the coordinator.
+const PROGRESS_SEND_INTERVAL: Duration = Duration::from_secs(5);
+/// The most threads a worker process can join with. Each one adds a core to the progress
+/// counters, so a bad hello must not be able to ask for any number.
+const MAX_WORKER_THREADS: usize = 1024;
+
+static NEXT_UNIT: AtomicU64 = AtomicU64::new(1);
+
+/// The jobs a worker process is running, by unit, with when they were sent. Once the connection
+/// has closed no more are added, and those left are handed back.
+#[derive(Default)]
+struct InFlight {
+    units: HashMap<u64, (Job, Instant)>,
+    closed: bool,
+}
+
+/// The progress counters each worker process was given, by name, so that one that joins again
+/// takes up its old cores rather than adding more.
+#[derive(Default)]
+struct Cores {
+    by_name: HashMap<String, JoinedCores>,
+}
+
+struct JoinedCores {
+    first: usize,
+    threads: usize,
+    connected: bool,
+}
+
+impl Cores {
+    /// Returns the first of `threads` cores for the worker, and whether they are recorded under
+    /// its name to be given back with `leave`.
+    fn join(&mut self, progress: &Progress, name: &str, threads: usize) -> (usize, bool) {
+        if let Some(joined) = self.by_name.get_mut(name) {
+            if !joined.connected && threads <= joined.threads {
+                joined.connected = true;
+                return (joined.first, true);
+            }
+            // A second process of the same name gets cores of its own, which are not recorded.
+            // One that comes back with more threads is given new cores in place of its old ones.
+            if joined.connected {
+                return (progress.add_workers(threads), false);
+            }
+        }
+        let first = progress.add_workers(threads);
+        let joined = JoinedCores {
+            first,
+            threads,
+            connected: true,
+        };
+        self.by_name.insert(name.to_string(), joined);
+        (first, true)
+    }
+
+    fn leave(&mut self, name: &str) {
+        if let Some(joined) = self.by_name.get_mut(name) {
+            joined.connected = false;
+        }
+    }
+}
+
+/// A worker process's hold on the cores recorded under its name, which are given back however
+/// its connection ends.
+struct CoreLease<'a> {
+    cores: &'a Mutex<Cores>,
+    name: Option<String>,
+}
+
+impl Drop for CoreLease<'_> {
+    fn drop(&mut self) {
+        if let Some(name) = &self.name {
+            self.cores.lock().unwrap().leave(name);
+        }
+    }
+}
+
+/// What the coordinator shares with every connection.
+#[derive(Clone)]
+pub struct CoordinatorContext {
+    pub puzzle: Puzzle,
+    pub config: SolverConfig,
+    pub max_node_count: u64,
+    pub sinks: Arc<Vec<Box<dyn ResultSink>>>,
+    pub progress: Arc<Progress>,
+    /// Where the state of units out with worker processes is written, as local searches write
+    /// theirs.
+    pub checkpoint: Option<Arc<Checkpoint>>,
+}
+
+/// Accepts worker processes on `listener` and hands each of them jobs from `jobs` as its threads
+/// come free, until the run is stopped. Their results go to `results` like those of the local
+/// workers, and jobs still out when a worker process goes away are handed back to the queue.
+pub fn serve_workers(
+    listener: TcpListener,
+    jobs: Arc<JobQueue>,
+    results: Sender<JobResult>,
+    context: CoordinatorContext,
+) -> io::Result<()> {
+    // Polled, so that the listener notices the run stopping and lets go of `results`.
+    listener.set_nonblocking(true)?;
+    std::thread::spawn(move || {
+        // Only the connections still open, since each one's thread holds its writer until the end.
+        let mut connections: Vec<Weak<Mutex<TcpStream>>> = vec![];
+        let cores = Arc::new(Mutex::new(Cores::default()));
+        loop {
+            connections.retain(|x| x.strong_count() > 0);
+            match listener.accept() {
+                Ok((stream, addr)) => {
+                    let writer = stream
+                        .set_nonblocking(false)
+                        .and_then(|_| stream.try_clone());
+                    let writer = match writer {
+                        Ok(writer) => Arc::new(Mutex::new(writer)),
+                        Err(e) => {
+                            warn!("Failed to accept worker at {addr}: {e}");
+                            continue;
+                        }
+                    };
+                    connections.push(Arc::downgrade(&writer));
+                    let cores = Arc::clone(&cores);
+                    let jobs = Arc::clone(&jobs);
+                    let results = results.clone();
+                    let context = context.clone();
+                    std::thread::spawn(move || {
+                        let in_flight = Arc::new(Mutex::new(InFlight::default()));
+                        let result = handle_worker(
+                            stream, &writer, &jobs, &results, &context, &in_flight, &cores,
+                        );
+                        if let Err(e) = result {
+                            warn!("Worker at {addr}: {e}");
+                        }
+                        // The listener keeps a copy of the stream, so it is closed explicitly.
+                        let _ = writer.lock().unwrap().shutdown(Shutdown::Both);
+                        let unfinished: Vec<_> = {
+                            let mut in_flight = in_flight.lock().unwrap();
+                            in_flight.closed = true;
+                            in_flight.units.drain().map(|(_, (job, _))| job).collect()
+                        };
+                        if !unfinished.is_empty() {
+                            info!(
+                                "Worker at {addr} left with {} searches unfinished, handing them back",
+                                unfinished.len()
+                            );
+                        }
+                        for job in unfinished {
+                            jobs.give_back(job);
+                        }
+                    });
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    if STOP.load(Ordering::Relaxed) {
+                        for writer in connections.iter().filter_map(Weak::upgrade) {
+                            let _ = write_message(&mut *writer.lock().unwrap(), &ToWorker::Stop);
+                        }
+                        return;
+                    }
+                    std::thread::sleep(POLL_INTERVAL);
+                }
+                Err(e) => warn!("Failed to accept a worker: {e}"),
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Talks to one worker process until it disconnects. Jobs are handed out from a thread of
+/// their own, so that waiting for the next one never holds up reading boards and results.
+fn handle_worker(
+    stream: TcpStream,
+    writer: &Arc<Mutex<TcpStream>>,
+    jobs: &Arc<JobQueue>,
+    results: &Sender<JobResult>,
+    context: &CoordinatorContext,
+    in_flight: &Arc<Mutex<InFlight>>,
+    cores: &Mutex<Cores>,
+) -> io::Result<()> {
+    let mut reader = BufReader::new(stream);
+    let Some(ToCoordinator::Hello { name, threads }) = read_message(&mut reader)? else {
+        return Err(invalid_data("expected a hello"));
+    };
+    if !(1..=MAX_WORKER_THREADS).contains(&threads) {
+        return Err(invalid_data(&format!(
+            "worker {name} asked for {threads} threads, but between 1 and {MAX_WORKER_THREADS} are allowed"
+        )));
+    }
+    let (first, recorded) = cores
+        .lock()
+        .unwrap()
+        .join(&context.progress, &name, threads);
+    let _lease = CoreLease {
+        cores,
+        name: recorded.then(|| name.clone()),
+    };
+    info!(
+        "Worker {name} joined with {threads} threads as cores {first:02} to {:02}",
+        first + threads - 1
+    );
+    write_message(
+        &mut *writer.lock().unwrap(),
+        &ToWorker::Setup {
+            puzzle: context.puzzle.clone(),
+            config: context.config.clone(),
+        },
+    )?;
+    let num_cells = context.puzzle.num_cells();
+    let mut published = vec![vec![0; num_cells + 1]; threads];
+    let core = |thread: usize| {
+        if thread < threads {
+            Ok(first + thread)
+        } else {
+            Err(invalid_data("unknown thread"))
+        }
+    };
+
+    // Ends once the last `Ready` has been answered and `ready` is dropped with this function.
+    let (ready, readies) = mpsc::channel();
+    {
+        let (writer, jobs, in_flight) =
+            (Arc::clone(writer), Arc::clone(jobs), Arc::clone(in_flight));
+        let max_node_count = context.max_node_count;
+        std::thread::spawn(move || {
+            dispatch_jobs(readies, &writer, &jobs, max_node_count, &in_flight)
+        });
+    }
+
+    while let Some(message) = read_message(&mut reader)? {
+        match message {
+            ToCoordinator::Ready => {
+                let _ = ready.send(());
+            }
+            ToCoordinator::Board {
+                unit,
+                thread,
+                board,
+            } => {
+                let core = core(thread)?;
+                let Some((solver_data, search_seed)) = in_flight
+                    .lock()
+                    .unwrap()
+                    .units
+                    .get(&unit)
+                    .map(|(job, _)| (Arc::clone(&job.solver_data), job.seed))
+                else {
+                    return Err(invalid_data("board for an unknown unit"));
+                };
+                if board.len() != num_cells {
+                    return Err(invalid_data("board of the wrong size"));
+                }
+                let found = FoundBoard {
+                    solver_data: &solver_data,
+                    board: &board,
+                    worker: core,
+                    search_seed,
+                };
+                match context.sinks.send(&found) {
+                    Ok(()) => context.progress.worker(core).record_board_saved(),
+                    Err(e) => error!("Worker {name}: failed to save board: {e}"),
+                }
+            }
+            ToCoordinator::Progress { threads } => {
+                for (thread, (x, published)) in threads.iter().zip(&mut published).enumerate() {
+                    context.progress.worker(first + thread).publish(
+                        x.depth,
+                        x.best_depth,
+                        &x.solve_index_counts,
+                        published,
+                    );
+                }
+            }
+            ToCoordinator::State {
+                unit,
+                thread,
+                state,
+            } => {
+                core(thread)?;
+                if state.board.len() != num_cells {
+                    return Err(invalid_data("state of the wrong size"));
+                }
+                let mut in_flight = in_flight.lock().unwrap();
+                let Some((job, _)) = in_flight.units.get_mut(&unit) else {
+                    return Err(invalid_data("state for an unknown unit"));
+                };
+                if let Some(checkpoint) = &context.checkpoint {
+                    let result = checkpoint.save_job(&JobCheckpoint {
+                        loop_count: job.loop_count,
+                        slot: job.slot,
+                        repeat: job.repeat,
+                        state: state.clone(),
+                    });
+                    if let Err(e) = result {
+                        warn!("Worker {name}: failed to write checkpoint: {e}");
+                    }
+                }
+                // Handed back from here if the worker goes.
+                job.state = Some(state);
+            }
+            ToCoordinator::Done {
+                unit,
+                thread,
+                result,
+            } => {
+                let core = core(thread)?;
+                let Some((job, sent)) = in_flight.lock().unwrap().units.remove(&unit) else {
+                    return Err(invalid_data("result for an unknown unit"));
+                };
+                context.progress.worker(core).record_search();
+                // A worker process that was stopped on its own leaves its search for another.
+                let stopped = result.stopped && !STOP.load(Ordering::Relaxed);
+                let job_result = JobResult {
+                    loop_count: job.loop_count,
+                    slots: job.slots,
+                    slot: job.slot,
+                    repeat: job.repeat,
+                    worker: core,
+                    solver_result: result,
+                    seconds: sent.elapsed().as_secs(),
+                };
+                if stopped {
+                    jobs.give_back(job);
+                }
+                if results.send(job_result).is_err() {
+                    return Ok(());
+                }
+            }
+            ToCoordinator::Hello { .. } => return Err(invalid_data("unexpected hello")),
+        }
+    }
+    Ok(())
+}
+
+/// Sends a worker process a unit for each `Ready` it sent, as jobs become available, until the
+/// connection closes.
+fn dispatch_jobs(
+    readies: Receiver<()>,
+    writer: &Mutex<TcpStream>,
+    jobs: &JobQueue,
+    max_node_count: u64,
+    in_flight: &Mutex<InFlight>,
+) {
+    for () in readies {
+        if STOP.load(Ordering::Relaxed) {
+            continue;
+        }
+        let Some(job) = jobs.next() else {
+            return;
+        };
+        let unit = WorkUnit {
+            id: NEXT_UNIT.fetch_add(1, Ordering::Relaxed),
+            solver_data_seed: job.solver_data.seed,
+            search_seed: job.seed,
+            max_node_count,
+            state: job.state.clone(),
+        };
+        {
+            let mut in_flight = in_flight.lock().unwrap();
+            // The worker may have gone while this waited for the job, in which case it is
+            // handed straight back.
+            if in_flight.closed || STOP.load(Ordering::Relaxed) {
+                drop(in_flight);
+                jobs.give_back(job);
+                continue;
+            }
+            in_flight.units.insert(unit.id, (job, Instant::now()));
+        }
+        // If the worker has gone the reader sees the connection close and hands the job back.
+        if write_message(&mut *writer.lock().unwrap(), &ToWorker::Unit(unit)).is_err() {
+            return;
+        }
+    }
+}
+
+/// Sends each deep board to the coordinator.
+struct CoordinatorSink {
+    writer: Arc<Mutex<TcpStream>>,
+    unit: u64,
+    thread: usize,
+}
+
+impl ResultSink for CoordinatorSink {
+    fn send(&self, found: &FoundBoard) -> io::Result<()> {
+        let message = ToCoordinator::Board {
+            unit: self.unit,
+            thread: self.thread,
+            board: found.board.to_vec(),
+        };
+        write_message(&mut *self.writer.lock().unwrap(), &message)
+    }
+}
+
+/// Joins the coordinator at `addr` and runs the units it sends on `threads` threads until it
+/// stops the run or goes away, or this process is stopped.
+pub fn work(addr: &str, name: String, threads: usize, progress_interval: u64) -> io::Result<()> {
+    let stream = TcpStream::connect(addr)?;
+    let writer = Arc::new(Mutex::new(stream.try_clone()?));
+    let mut reader = BufReader::new(stream.try_clone()?);
+    write_message(
+        &mut *writer.lock().unwrap(),
+        &ToCoordinator::Hello {
+            name: name.clone(),
+            threads,
+        },
+    )?;
+    let Some(ToWorker::Setup { puzzle, config }) = read_message(&mut reader)? else {
+        return Err(invalid_data("expected the setup"));
+    };
+    info!(
+        "Joined coordinator at {addr} as {name} with {threads} threads, board is {}x{}",
+        puzzle.width, puzzle.height
+    );
+    let puzzle = Arc::new(puzzle);
+    let config = Arc::new(config);
+    let progress = Arc::new(Progress::new(threads, puzzle.num_cells()));
+    if progress_interval > 0 {
+        spawn_reporter(Arc::clone(&progress), progress_interval);
+    }
+
+    // Units are read on their own thread, so that this one can close the connection once every
+    // search has stopped.
+    let (unit_sender, units) = mpsc::channel();
+    std::thread::spawn(move || {
+        loop {
+            match read_message(&mut reader) {
+                Ok(Some(ToWorker::Unit(unit))) => {
+                    if unit_sender.send(unit).is_err() {
+                        break;
+                    }
+                }
+                Ok(Some(ToWorker::Stop)) => {
+                    info!("Coordinator stopped the run");
+                    break;
+                }
+                Ok(Some(ToWorker::Setup { .. })) => warn!("Ignoring a second setup"),
+                Ok(None) => {
+                    warn!("Coordinator closed the connection");
+                    break;
+                }
+                Err(e) => {
+                    error!("Failed to read from the coordinator: {e}");
+                    break;
+                }
+            }
+        }
+        STOP.store(true, Ordering::Relaxed);
+    });
+
+    {
+        let progress = Arc::clone(&progress);
+        let writer = Arc::clone(&writer);
+        std::thread::spawn(move || loop {
+            std::thread::sleep(PROGRESS_SEND_INTERVAL);
+            if send_progress(&writer, &progress, threads).is_err() {
+                return;
+            }
+        });
+    }
+
+    let units = Arc::new(Mutex::new(units));
+    let solver_data: Arc<Mutex<Option<Arc<SolverData>>>> = Arc::new(Mutex::new(None));
+    let handles: Vec<_> = (0..threads)
+        .map(|thread| {
+            let units = Arc::clone(&units);
+            let writer = Arc::clone(&writer);
+            let progress = Arc::clone(&progress);
+            let solver_data = Arc::clone(&solver_data);
+            let (puzzle, config) = (Arc::clone(&puzzle), Arc::clone(&config));
+            std::thread::spawn(move || -> io::Result<()> {
+                loop {
+                    write_message(&mut *writer.lock().unwrap(), &ToCoordinator::Ready)?;
+                    let unit: WorkUnit = loop {
+                        if STOP.load(Ordering::Relaxed) {
+                            return Ok(());
+                        }
+                        match units.lock().unwrap().recv_timeout(POLL_INTERVAL) {
+                            Ok(unit) => break unit,
+                            Err(RecvTimeoutError::Timeout) => {}
+                            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+                        }
+                    };
+
+                    // Consecutive units usually share their solver data.
+                    let unit_solver_data = {
+                        let mut cached = solver_data.lock().unwrap();
+                        match &*cached {
+                            Some(x) if x.seed == unit.solver_data_seed => Arc::clone(x),
+                            _ => {
+                                let built = Arc::new(prepare_pieces_and_heuristics(
+                                    &puzzle,
+                                    &config,
+                                    unit.solver_data_seed,
+                                ));
+                                *cached = Some(Arc::clone(&built));
+                                built
+                            }
+                        }
+                    };
+                    let mut state = unit.state.unwrap_or_else(|| {
+                        SearchState::new(&unit_solver_data, thread, unit.search_seed)
+                    });
+                    state.worker = thread;
+                    let sink = CoordinatorSink {
+                        writer: Arc::clone(&writer),
+                        unit: unit.id,
+                        thread,
+                    };
+                    let send_state = |state: SearchState| {
+                        let message = ToCoordinator::State {
+                            unit: unit.id,
+                            thread,
+                            state,
+                        };
+                        if let Err(e) = write_message(&mut *writer.lock().unwrap(), &message) {
+                            warn!("Core {thread:02}: failed to send the search state: {e}");
+                        }
+                    };
+                    let result = solve_puzzle(
+                        &unit_solver_data,
+                        state,
+                        unit.max_node_count,
+                        Some(&sink),
+                        Some(&progress.worker(thread)),
+                        Some(&STOP),
+                        Some(&send_state),
+                    );
+                    info!(
+                        "Core {thread:02}: {} unit {}, best depth {}",
+                        if result.stopped {
+                            "stopped"
+                        } else {
+                            "finished"
+                        },
+                        unit.id,
+                        result.max_depth
+                    );
+                    send_progress(&writer, &progress, threads)?;
+                    write_message(
+                        &mut *writer.lock().unwrap(),
+                        &ToCoordinator::Done {
+                            unit: unit.id,
+                            thread,
+                            result,
+                        },
+                    )?;
+                }
+            })
+        })
+        .collect();
+
+    for handle in handles {
+        if let Err(e) = handle.join().unwrap() {
+            error!("Failed to write to the coordinator: {e}");
+            STOP.store(true, Ordering::Relaxed);
+        }
+    }
+    let _ = send_progress(&writer, &progress, threads);
+    let _ = writer.lock().unwrap().shutdown(Shutdown::Both);
+    info!("Left the coordinator");
+    Ok(())
+}
+
+fn send_progress(writer: &Mutex<TcpStream>, progress: &Progress, threads: usize) -> io::Result<()> {
+    let threads = (0..threads)
+        .map(|x| {
+            let worker = progress.worker(x);
+            let snapshot = worker.snapshot();
+            ThreadProgress {
+                depth: snapshot.depth,
+                best_depth: snapshot.best_depth,
+                solve_index_counts: worker.solve_index_counts(),
+            }
+        })
+        .collect();
+    write_message(
+        &mut *writer.lock().unwrap(),
+        &ToCoordinator::Progress { threads },
+    )
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generate::generate_puzzle;
+    use std::fs;
+    use std::sync::mpsc::SyncSender;
+
+    struct Coordinator {
+        addr: String,
+        progress: Arc<Progress>,
+        jobs: Arc<JobQueue>,
+        feeder: SyncSender<Job>,
+        solver_data: Arc<SolverData>,
+    }
+
+    fn coordinator(checkpoint: Option<Arc<Checkpoint>>) -> Coordinator {
+        let puzzle = generate_puzzle(4, 4, 3, 4, 2).unwrap().puzzle;
+        let config = SolverConfig::for_board(4, 4);
+        let solver_data = Arc::new(prepare_pieces_and_heuristics(&puzzle, &config, 1));
+        let progress = Arc::new(Progress::new(0, puzzle.num_cells()));
+        let (feeder, jobs) = mpsc::sync_channel(0);
+        let jobs = Arc::new(JobQueue::new(jobs));
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let context = CoordinatorContext {
+            puzzle,
+            config,
+            max_node_count: 1000,
+            sinks: Arc::new(vec![]),
+            progress: Arc::clone(&progress),
+            checkpoint,
+        };
+        // The results are not read, so the receiver can go.
+        let (results, _) = mpsc::channel();
+        serve_workers(listener, Arc::clone(&jobs), results, context).unwrap();
+        Coordinator {
+            addr,
+            progress,
+            jobs,
+            feeder,
+            solver_data,
+        }
+    }
+
+    /// Joins as a worker process and waits for the setup.
+    fn join(addr: &str, name: &str, threads: usize) -> (TcpStream, BufReader<TcpStream>) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let hello = ToCoordinator::Hello {
+            name: name.to_string(),
+            threads,
+        };
+        write_message(&mut stream, &hello).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let setup: Option<ToWorker> = read_message(&mut reader).unwrap();
+        assert!(matches!(setup, Some(ToWorker::Setup { .. })));
+        (stream, reader)
+    }
+
+    /// Leaves, giving the coordinator time to see the connection close.
+    fn leave(stream: TcpStream) {
+        stream.shutdown(Shutdown::Both).unwrap();
+        std::thread::sleep(Duration::from_millis(300));
+    }
+
+    #[test]
+    fn workers_that_join_again_take_up_their_old_cores() {
+        let coordinator = coordinator(None);
+        let cores = || coordinator.progress.snapshot().workers.len();
+        let (first, _) = join(&coordinator.addr, "first", 2);
+        assert_eq!(cores(), 2);
+        leave(first);
+        let (first, _) = join(&coordinator.addr, "first", 2);
+        assert_eq!(cores(), 2);
+
+        // A second process of the same name, and one of another name, get cores of their own.
+        let (_same_name, _) = join(&coordinator.addr, "first", 1);
+        assert_eq!(cores(), 3);
+        let (_second, _) = join(&coordinator.addr, "second", 3);
+        assert_eq!(cores(), 6);
+        leave(first);
+        let (_first, _) = join(&coordinator.addr, "first", 1);
+        assert_eq!(cores(), 6);
+    }
+
+    #[test]
+    fn units_out_with_workers_are_checkpointed_and_handed_back_from_their_state() {
+        let dir =
+            std::env::temp_dir().join(format!("eternity-solver-remote-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let checkpoint = Arc::new(Checkpoint::new(&dir).unwrap());
+        let coordinator = coordinator(Some(Arc::clone(&checkpoint)));
+        let job = Job {
+            loop_count: 3,
+            slots: 2,
+            slot: 1,
+            repeat: 2,
+            seed: 4,
+            solver_data: Arc::clone(&coordinator.solver_data),
+            state: None,
+        };
+        let feeder = coordinator.feeder.clone();
+        std::thread::spawn(move || feeder.send(job).unwrap());
+
+        let (mut stream, mut reader) = join(&coordinator.addr, "worker", 1);
+        write_message(&mut stream, &ToCoordinator::Ready).unwrap();
+        let Some(ToWorker::Unit(unit)) = read_message(&mut reader).unwrap() else {
+            panic!("expected a unit");
+        };
+        assert!(unit.state.is_none());
+        let state = SearchState::new(&coordinator.solver_data, 0, 5);
+        let message = ToCoordinator::State {
+            unit: unit.id,
+            thread: 0,
+            state: state.clone(),
+        };
+        write_message(&mut stream, &message).unwrap();
+        leave(stream);
+
+        let saved = checkpoint.load_job(3, 1, 2).unwrap().unwrap();
+        assert_eq!(saved.state.board, state.board);
+        let handed_back = coordinator.jobs.next().unwrap();
+        assert_eq!((handed_back.slot, handed_back.repeat), (1, 2));
+        assert_eq!(handed_back.state.unwrap().board, state.board);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,481 @@
+use crate::board_file::load_boards;
+use crate::puzzle::Puzzle;
+use crate::structs::{Piece, RotatedPiece};
+use log::info;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Fill colours for edge colours 0 to 22, grey first. Higher colours reuse the list.
+const PALETTE: [[u8; 3]; 23] = [
+    [128, 128, 128],
+    [230, 25, 75],
+    [60, 180, 75],
+    [255, 225, 25],
+    [0, 130, 200],
+    [245, 130, 48],
+    [145, 30, 180],
+    [70, 240, 240],
+    [240, 50, 230],
+    [210, 245, 60],
+    [250, 190, 212],
+    [0, 128, 128],
+    [220, 190, 255],
+    [170, 110, 40],
+    [255, 250, 200],
+    [128, 0, 0],
+    [170, 255, 195],
+    [128, 128, 0],
+    [255, 215, 180],
+    [0, 0, 128],
+    [255, 255, 255],
+    [0, 0, 0],
+    [255, 99, 71],
+];
+const EMPTY_CELL: [u8; 3] = [40, 40, 40];
+const GRID_LINE: [u8; 3] = [20, 20, 20];
+const BREAK_LINE: [u8; 3] = [255, 0, 0];
+const LABEL: [u8; 3] = [255, 255, 255];
+
+/// 3x5 glyphs for the digits and `/`, one row per byte with the leftmost pixel in bit 2.
+const GLYPHS: [(char, [u8; 5]); 11] = [
+    ('0', [7, 5, 5, 5, 7]),
+    ('1', [2, 6, 2, 2, 7]),
+    ('2', [7, 1, 7, 4, 7]),
+    ('3', [7, 1, 7, 1, 7]),
+    ('4', [5, 5, 7, 1, 1]),
+    ('5', [7, 4, 7, 1, 7]),
+    ('6', [7, 4, 7, 5, 7]),
+    ('7', [7, 1, 1, 1, 1]),
+    ('8', [7, 5, 7, 5, 7]),
+    ('9', [7, 5, 7, 1, 7]),
+    ('/', [1, 1, 2, 4, 4]),
+];
+
+#[derive(Debug, Clone, Copy)]
+pub struct RenderOptions {
+    /// Width and height of each cell in pixels.
+    pub cell_size: u32,
+    /// Write each piece's `reid` on it.
+    pub show_ids: bool,
+    /// Write each piece's rotations on it, after its `reid` when both are shown.
+    pub show_rotations: bool,
+    /// Draw unmatched edges between neighbouring pieces in red.
+    pub highlight_breaks: bool,
+}
+
+impl Default for RenderOptions {
+    fn default() -> RenderOptions {
+        RenderOptions {
+            cell_size: 48,
+            show_ids: false,
+            show_rotations: false,
+            highlight_breaks: true,
+        }
+    }
+}
+
+/// The text written on a piece, if the options ask for any.
+fn label(piece: &RotatedPiece, options: &RenderOptions) -> Option<String> {
+    match (options.show_ids, options.show_rotations) {
+        (true, true) => Some(format!("{}/{}", piece.reid, piece.rotations)),
+        (true, false) => Some(piece.reid.to_string()),
+        (false, true) => Some(piece.rotations.to_string()),
+        (false, false) => None,
+    }
+}
+
+/// The edges between neighbouring pieces whose colours differ, as the board index of the lower
+/// or left piece and whether the edge is on its top (rather than its right).
+fn breaks(puzzle: &Puzzle, placed: &[Option<Piece>]) -> Vec<(usize, bool)> {
+    let width = puzzle.width;
+    let mut breaks = vec![];
+    for row in 0..puzzle.height {
+        for col in 0..width {
+            let index = row * width + col;
+            let Some(piece) = placed[index] else {
+                continue;
+            };
+            if col + 1 < width {
+                if let Some(other) = placed[index + 1] {
+                    if other.left != piece.right {
+                        breaks.push((index, false));
+                    }
+                }
+            }
+            if row + 1 < puzzle.height {
+                if let Some(other) = placed[index + width] {
+                    if other.bottom != piece.top {
+                        breaks.push((index, true));
+                    }
+                }
+            }
+        }
+    }
+    breaks
+}
+
+fn colour(index: u8) -> [u8; 3] {
+    PALETTE[index as usize % PALETTE.len()]
+}
+
+fn hex(rgb: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", rgb[0], rgb[1], rgb[2])
+}
+
+/// Draws a board as an SVG image, each piece split into four triangles coloured by its edges.
+pub fn render_svg(puzzle: &Puzzle, board: &[RotatedPiece], options: &RenderOptions) -> String {
+    let size = options.cell_size as usize;
+    let (width, height) = (puzzle.width, puzzle.height);
+    let placed: Vec<Option<Piece>> = board.iter().map(|x| puzzle.placed_piece(x)).collect();
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\">\n",
+        width * size,
+        height * size
+    );
+
+    for row in 0..height {
+        for col in 0..width {
+            // Row 0 is the bottom of the board but the top of the image.
+            let (x, y) = (col * size, (height - 1 - row) * size);
+            let Some(piece) = placed[row * width + col] else {
+                let _ = writeln!(
+                    svg,
+                    "<rect x=\"{x}\" y=\"{y}\" width=\"{size}\" height=\"{size}\" fill=\"{}\"/>",
+                    hex(EMPTY_CELL)
+                );
+                continue;
+            };
+            let (right, bottom) = (x + size, y + size);
+            let (cx, cy) = (x + size / 2, y + size / 2);
+            let triangles = [
+                (piece.top, [(x, y), (right, y)]),
+                (piece.right, [(right, y), (right, bottom)]),
+                (piece.bottom, [(right, bottom), (x, bottom)]),
+                (piece.left, [(x, bottom), (x, y)]),
+            ];
+            for (side, [(x1, y1), (x2, y2)]) in triangles {
+                let _ = writeln!(
+                    svg,
+                    "<polygon points=\"{x1},{y1} {x2},{y2} {cx},{cy}\" fill=\"{}\" stroke=\"{}\" stroke-width=\"0.5\"/>",
+                    hex(colour(side)),
+                    hex(GRID_LINE)
+                );
+            }
+            if let Some(label) = label(&board[row * width + col], options) {
+                let _ = writeln!(
+                    svg,
+                    "<text x=\"{cx}\" y=\"{cy}\" font-family=\"monospace\" font-size=\"{}\" text-anchor=\"middle\" dominant-baseline=\"central\" fill=\"{}\" stroke=\"{}\" stroke-width=\"0.4\">{label}</text>",
+                    size / 4,
+                    hex(LABEL),
+                    hex(GRID_LINE),
+                );
+            }
+        }
+    }
+
+    if options.highlight_breaks {
+        for (index, on_top) in breaks(puzzle, &placed) {
+            let (row, col) = (index / width, index % width);
+            let (x, y) = (col * size, (height - 1 - row) * size);
+            let (x1, y1, x2, y2) = if on_top {
+                (x, y, x + size, y)
+            } else {
+                (x + size, y, x + size, y + size)
+            };
+            let _ = writeln!(
+                svg,
+                "<line x1=\"{x1}\" y1=\"{y1}\" x2=\"{x2}\" y2=\"{y2}\" stroke=\"{}\" stroke-width=\"{}\"/>",
+                hex(BREAK_LINE),
+                2 * (size / 16).max(1)
+            );
+        }
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Draws the same picture as `render_svg` straight into a PNG image.
+pub fn render_png(
+    puzzle: &Puzzle,
+    board: &[RotatedPiece],
+    options: &RenderOptions,
+) -> io::Result<Vec<u8>> {
+    let size = options.cell_size as usize;
+    let (width, height) = (puzzle.width, puzzle.height);
+    let (image_width, image_height) = (width * size, height * size);
+    let placed: Vec<Option<Piece>> = board.iter().map(|x| puzzle.placed_piece(x)).collect();
+    let mut pixels = vec![0u8; image_width * image_height * 3];
+    let mut set_pixel = |x: usize, y: usize, rgb: [u8; 3]| {
+        let offset = (y * image_width + x) * 3;
+        pixels[offset..offset + 3].copy_from_slice(&rgb);
+    };
+
+    for row in 0..height {
+        for col in 0..width {
+            let (x0, y0) = (col * size, (height - 1 - row) * size);
+            let piece = placed[row * width + col];
+            for dy in 0..size {
+                for dx in 0..size {
+                    let rgb = match piece {
+                        _ if dx == 0 || dy == 0 => GRID_LINE,
+                        None => EMPTY_CELL,
+                        Some(piece) => {
+                            // Which of the four triangles meeting in the middle holds the pixel.
+                            let (x, y) = (
+                                2 * dx as i64 + 1 - size as i64,
+                                2 * dy as i64 + 1 - size as i64,
+                            );
+                            let side = if y.abs() >= x.abs() {
+                                if y < 0 {
+                                    piece.top
+                                } else {
+                                    piece.bottom
+                                }
+                            } else if x > 0 {
+                                piece.right
+                            } else {
+                                piece.left
+                            };
+                            colour(side)
+                        }
+                    };
+                    set_pixel(x0 + dx, y0 + dy, rgb);
+                }
+            }
+
+            if let Some(label) = piece.and(label(&board[row * width + col], options)) {
+                let scale = (size / 24).max(1);
+                let label_width = (label.len() * 4 - 1) * scale;
+                let left = x0 + size.saturating_sub(label_width) / 2;
+                let top = y0 + size.saturating_sub(5 * scale) / 2;
+                // A dark box behind the label keeps it readable on light edges.
+                for y in top.saturating_sub(scale)..(top + 6 * scale).min(image_height) {
+                    for x in
+                        left.saturating_sub(scale)..(left + label_width + scale).min(image_width)
+                    {
+                        set_pixel(x, y, GRID_LINE);
+                    }
+                }
+                for (i, c) in label.chars().enumerate() {
+                    let Some((_, glyph)) = GLYPHS.iter().find(|x| x.0 == c) else {
+                        continue;
+                    };
+                    for (gy, bits) in glyph.iter().enumerate() {
+                        for gx in 0..3 {
+                            if bits & (4 >> gx) == 0 {
+                                continue;
+                            }
+                            for sy in 0..scale {
+                                for sx in 0..scale {
+                                    let x = left + (i * 4 + gx) * scale + sx;
+                                    let y = top + gy * scale + sy;
+                                    if x < image_width && y < image_height {
+                                        set_pixel(x, y, LABEL);
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    if options.highlight_breaks {
+        let thickness = (size / 16).max(1);
+        for (index, on_top) in breaks(puzzle, &placed) {
+            let (row, col) = (index / width, index % width);
+            let (x0, y0) = (col * size, (height - 1 - row) * size);
+            for along in 0..size {
+                for across in 0..2 * thickness {
+                    // Straddle the shared edge, half in each piece.
+                    let (x, y) = if on_top {
+                        (x0 + along, (y0 + across).checked_sub(thickness))
+                    } else {
+                        (x0 + size + across - thickness, Some(y0 + along))
+                    };
+                    if let Some(y) = y {
+                        if x < image_width && y < image_height {
+                            set_pixel(x, y, BREAK_LINE);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    let mut png_bytes = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png_bytes, image_width as u32, image_height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(io::Error::other)?;
+    writer.write_image_data(&pixels).map_err(io::Error::other)?;
+    writer.finish().map_err(io::Error::other)?;
+    Ok(png_bytes)
+}
+
+/// Draws each board in a board file as an image, to `output` or else next to the file as an SVG.
+/// A PNG is drawn when `output` ends in `.png`.
+pub fn run_render(
+    puzzle: &Puzzle,
+    file: &Path,
+    output: Option<&Path>,
+    options: &RenderOptions,
+) -> Result<(), String> {
+    let board_files = load_boards(file).map_err(|e| format!("{}: {e}", file.display()))?;
+    let output = output.map_or_else(|| file.with_extension("svg"), Path::to_path_buf);
+
+    for (i, board_file) in board_files.iter().enumerate() {
+        if board_file.width != puzzle.width || board_file.height != puzzle.height {
+            return Err(format!(
+                "{}: board is {}x{} but the puzzle is {}x{}",
+                file.display(),
+                board_file.width,
+                board_file.height,
+                puzzle.width,
+                puzzle.height
+            ));
+        }
+
+        // Each board in a `boards.jsonl` file gets its own image, numbered from 1.
+        let output = match board_files.len() {
+            1 => output.clone(),
+            _ => {
+                let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+                let extension = output.extension().unwrap_or_default().to_string_lossy();
+                output.with_file_name(format!("{stem}_{}.{extension}", i + 1))
+            }
+        };
+        let result = if output.extension().is_some_and(|x| x == "png") {
+            render_png(puzzle, &board_file.board, options).and_then(|x| fs::write(&output, x))
+        } else {
+            fs::write(&output, render_svg(puzzle, &board_file.board, options))
+        };
+        result.map_err(|e| format!("Failed to write {}: {e}", output.display()))?;
+        info!("Wrote {}", output.display());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 2x2 board laid out as given, with the top of piece 2 not matching the bottom of piece 4.
+    fn broken_puzzle() -> (Puzzle, Vec<RotatedPiece>) {
+        let piece = |reid, top, right, bottom, left| Piece {
+            reid,
+            top,
+            right,
+            bottom,
+            left,
+        };
+        let puzzle = Puzzle::from_pieces(vec![
+            piece(1, 1, 2, 0, 0),
+            piece(2, 3, 0, 0, 2),
+            piece(3, 0, 4, 1, 0),
+            piece(4, 0, 0, 5, 4),
+        ])
+        .unwrap();
+        let board = (1..=4)
+            .map(|reid| RotatedPiece {
+                reid,
+                ..RotatedPiece::default()
+            })
+            .collect();
+        (puzzle, board)
+    }
+
+    fn decode(png_bytes: &[u8]) -> (u32, u32, Vec<u8>) {
+        let decoder = png::Decoder::new(io::Cursor::new(png_bytes));
+        let mut reader = decoder.read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size().unwrap()];
+        let info = reader.next_frame(&mut pixels).unwrap();
+        (info.width, info.height, pixels)
+    }
+
+    #[test]
+    fn labels_follow_the_options() {
+        let piece = RotatedPiece {
+            reid: 12,
+            rotations: 3,
+            ..RotatedPiece::default()
+        };
+        let options = |show_ids, show_rotations| RenderOptions {
+            show_ids,
+            show_rotations,
+            ..RenderOptions::default()
+        };
+        assert_eq!(label(&piece, &options(false, false)), None);
+        assert_eq!(label(&piece, &options(true, false)).unwrap(), "12");
+        assert_eq!(label(&piece, &options(false, true)).unwrap(), "3");
+        assert_eq!(label(&piece, &options(true, true)).unwrap(), "12/3");
+    }
+
+    #[test]
+    fn only_unmatched_edges_are_breaks() {
+        let (puzzle, board) = broken_puzzle();
+        let placed: Vec<_> = board.iter().map(|x| puzzle.placed_piece(x)).collect();
+        assert_eq!(breaks(&puzzle, &placed), [(1, true)]);
+
+        let mut placed = placed;
+        placed[3] = None;
+        assert!(breaks(&puzzle, &placed).is_empty());
+    }
+
+    #[test]
+    fn svg_draws_each_piece_and_break() {
+        let (puzzle, board) = broken_puzzle();
+        let options = RenderOptions {
+            cell_size: 10,
+            show_ids: true,
+            ..RenderOptions::default()
+        };
+        let svg = render_svg(&puzzle, &board, &options);
+        assert!(svg
+            .starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"20\" height=\"20\">"));
+        assert!(svg.ends_with("</svg>\n"));
+        assert_eq!(svg.matches("<polygon").count(), 16);
+        assert_eq!(svg.matches("<text").count(), 4);
+        assert!(svg.contains(">4</text>"));
+        // The top of piece 2, at the bottom right of the image.
+        assert!(svg.contains("<line x1=\"10\" y1=\"10\" x2=\"20\" y2=\"10\" stroke=\"#ff0000\""));
+
+        let svg = render_svg(
+            &puzzle,
+            &[RotatedPiece::default(); 4],
+            &RenderOptions::default(),
+        );
+        assert_eq!(svg.matches("<rect").count(), 4);
+        assert!(!svg.contains("<polygon") && !svg.contains("<line"));
+    }
+
+    #[test]
+    fn png_draws_the_same_picture() {
+        let (puzzle, board) = broken_puzzle();
+        let size = 16;
+        let options = RenderOptions {
+            cell_size: size as u32,
+            ..RenderOptions::default()
+        };
+        let pixel = |pixels: &[u8], x: usize, y: usize| {
+            let offset = (y * 2 * size + x) * 3;
+            [pixels[offset], pixels[offset + 1], pixels[offset + 2]]
+        };
+
+        let (width, height, pixels) = decode(&render_png(&puzzle, &board, &options).unwrap());
+        assert_eq!((width, height), (32, 32));
+        assert_eq!(pixel(&pixels, size + size / 2, size), BREAK_LINE);
+        assert_eq!(pixel(&pixels, size / 2, size), GRID_LINE);
+        // Piece 1 sits at the bottom left with colour 1 on its top and grey at the bottom.
+        assert_eq!(pixel(&pixels, size / 2, size + 2), colour(1));
+        assert_eq!(pixel(&pixels, size / 2, 2 * size - 2), colour(0));
+
+        let empty = render_png(&puzzle, &[RotatedPiece::default(); 4], &options).unwrap();
+        let (_, _, pixels) = decode(&empty);
+        assert_eq!(pixel(&pixels, size / 2, size / 2), EMPTY_CELL);
+    }
+}
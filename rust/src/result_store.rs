@@ -0,0 +1,330 @@
+use crate::checkpoint::write_json;
+use crate::config::{OutputFormat, PREFIX_LENGTH};
+use crate::solver_data::SolverData;
+use crate::structs::RotatedPiece;
+use crate::util::{board_record, board_text, format_board};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// One line of the store's log: a board found by a worker.
+#[derive(Serialize, Deserialize)]
+struct Find {
+    hash: String,
+    prefix: String,
+    depth: usize,
+    timestamp: String,
+    worker: usize,
+    search_seed: u64,
+    file: Option<String>,
+}
+
+/// A distinct board in the store.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BoardEntry {
+    pub depth: usize,
+    /// How many times the board has been found.
+    pub count: u64,
+    /// The board's file in the store, or `None` when boards go to `boards.jsonl`.
+    pub file: Option<String>,
+}
+
+/// What the store holds, keyed on board hashes. Written to `index.json` by `flush`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoreIndex {
+    pub boards: BTreeMap<String, BoardEntry>,
+    /// The deepest board found from each prefix, the first `PREFIX_LENGTH` placements in the
+    /// search order.
+    pub prefixes: BTreeMap<String, usize>,
+}
+
+struct StoreState {
+    index: StoreIndex,
+    log: File,
+}
+
+/// The boards found in a run, kept in the output directory. Every find is appended to
+/// `finds.jsonl`, from which the index is rebuilt when the store is opened, but each distinct
+/// board is only written once.
+pub struct ResultStore {
+    dir: PathBuf,
+    format: OutputFormat,
+    state: Mutex<StoreState>,
+}
+
+impl ResultStore {
+    pub fn open(dir: &Path, format: OutputFormat) -> io::Result<ResultStore> {
+        fs::create_dir_all(dir)?;
+        let log_path = dir.join("finds.jsonl");
+
+        let mut index = StoreIndex::default();
+        let mut valid_length = 0;
+        match fs::read_to_string(&log_path) {
+            Ok(text) => {
+                for (line_number, line) in text.split_inclusive('\n').enumerate() {
+                    let find = serde_json::from_str::<Find>(line)
+                        .ok()
+                        .filter(|_| line.ends_with('\n'));
+                    match find {
+                        Some(find) => record_find(&mut index, &find),
+                        // A crash mid-write can only tear the last line, which is dropped.
+                        None if valid_length + line.len() == text.len() => break,
+                        None => {
+                            return Err(io::Error::new(
+                                io::ErrorKind::InvalidData,
+                                format!(
+                                    "{} line {} is corrupt",
+                                    log_path.display(),
+                                    line_number + 1
+                                ),
+                            ))
+                        }
+                    }
+                    valid_length += line.len();
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+
+        let log = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&log_path)?;
+        // Held until the process exits, so two runs never write to the same store.
+        log.try_lock().map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::WouldBlock,
+                format!("{} is in use by another run", dir.display()),
+            )
+        })?;
+        log.set_len(valid_length as u64)?;
+        Ok(ResultStore {
+            dir: dir.to_path_buf(),
+            format,
+            state: Mutex::new(StoreState { index, log }),
+        })
+    }
+
+    /// Records that a worker found a board, writing the board out if it has not been found
+    /// before. Returns whether it was new.
+    pub fn save(
+        &self,
+        solver_data: &SolverData,
+        board: &[RotatedPiece],
+        worker: usize,
+        search_seed: u64,
+    ) -> io::Result<bool> {
+        let puzzle = &solver_data.puzzle;
+        let hash = format!("{:x}", md5::compute(format_board(puzzle, board)));
+        let prefix: String = solver_data
+            .board_search_sequence
+            .iter()
+            .take(PREFIX_LENGTH)
+            .map(|x| board[x.row as usize * puzzle.width + x.column as usize])
+            .map(|x| format!("{}/{} ", x.reid, x.rotations))
+            .collect();
+        let depth = board.iter().filter(|x| x.reid > 0).count();
+        let file = match self.format {
+            OutputFormat::Text => Some(format!("{depth}_{hash}.txt")),
+            OutputFormat::Json => Some(format!("{depth}_{hash}.json")),
+            OutputFormat::Jsonl => None,
+        };
+        let find = Find {
+            hash,
+            prefix: format!("{:x}", md5::compute(prefix)),
+            depth,
+            timestamp: Utc::now().to_rfc3339(),
+            worker,
+            search_seed,
+            file,
+        };
+
+        let mut state = self.state.lock().unwrap();
+        let is_new = !state.index.boards.contains_key(&find.hash);
+        if is_new {
+            let record =
+                || serde_json::to_string(&board_record(solver_data, board, worker, search_seed));
+            match &find.file {
+                Some(file) if self.format == OutputFormat::Text => fs::write(
+                    self.dir.join(file),
+                    board_text(solver_data, board, search_seed),
+                )?,
+                Some(file) => fs::write(self.dir.join(file), record()?)?,
+                None => {
+                    let mut boards = OpenOptions::new()
+                        .create(true)
+                        .append(true)
+                        .open(self.dir.join("boards.jsonl"))?;
+                    writeln!(boards, "{}", record()?)?;
+                }
+            }
+        }
+
+        writeln!(state.log, "{}", serde_json::to_string(&find)?)?;
+        record_find(&mut state.index, &find);
+        Ok(is_new)
+    }
+
+    /// Returns a copy of the index.
+    pub fn index(&self) -> StoreIndex {
+        self.state.lock().unwrap().index.clone()
+    }
+
+    /// Writes the index to `index.json` for other tools to read.
+    pub fn flush(&self) -> io::Result<()> {
+        let state = self.state.lock().unwrap();
+        state.log.sync_data()?;
+        write_json(&self.dir.join("index.json"), &state.index)
+    }
+}
+
+fn record_find(index: &mut StoreIndex, find: &Find) {
+    index
+        .boards
+        .entry(find.hash.clone())
+        .or_insert(BoardEntry {
+            depth: find.depth,
+            count: 0,
+            file: find.file.clone(),
+        })
+        .count += 1;
+    let best = index.prefixes.entry(find.prefix.clone()).or_insert(0);
+    *best = (*best).max(find.depth);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SolverConfig;
+    use crate::generate::generate_puzzle;
+    use crate::solver_data::prepare_pieces_and_heuristics;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "eternity-solver-store-{name}-{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    /// Solver data for a small puzzle, its solution and the solution less its last piece.
+    fn boards() -> (SolverData, Vec<RotatedPiece>, Vec<RotatedPiece>) {
+        let generated = generate_puzzle(4, 4, 3, 4, 2).unwrap();
+        let config = SolverConfig::for_board(4, 4);
+        let solver_data = prepare_pieces_and_heuristics(&generated.puzzle, &config, 1);
+        let mut partial = generated.solution.clone();
+        partial[15] = RotatedPiece::default();
+        (solver_data, generated.solution, partial)
+    }
+
+    #[test]
+    fn boards_found_again_are_counted_but_written_once() {
+        let dir = temp_dir("dedup");
+        let (solver_data, full, partial) = boards();
+        let store = ResultStore::open(&dir, OutputFormat::Text).unwrap();
+        assert!(store.save(&solver_data, &full, 0, 1).unwrap());
+        assert!(!store.save(&solver_data, &full, 1, 2).unwrap());
+        assert!(store.save(&solver_data, &partial, 0, 1).unwrap());
+
+        let index = store.index();
+        let mut entries: Vec<_> = index.boards.values().map(|x| (x.depth, x.count)).collect();
+        entries.sort_unstable();
+        assert_eq!(entries, [(15, 1), (16, 2)]);
+        // A prefix is longer than this board, so each board has one of its own.
+        let mut depths: Vec<_> = index.prefixes.values().copied().collect();
+        depths.sort_unstable();
+        assert_eq!(depths, [15, 16]);
+        let written = fs::read_dir(&dir)
+            .unwrap()
+            .filter(|x| x.as_ref().unwrap().path().extension().unwrap() == "txt")
+            .count();
+        assert_eq!(written, 2);
+
+        store.flush().unwrap();
+        let text = fs::read_to_string(dir.join("index.json")).unwrap();
+        assert_eq!(serde_json::from_str::<StoreIndex>(&text).unwrap(), index);
+
+        // The same index is rebuilt from the log when the store is opened again.
+        drop(store);
+        assert_eq!(
+            ResultStore::open(&dir, OutputFormat::Text).unwrap().index(),
+            index
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn json_lines_boards_are_appended_once() {
+        let dir = temp_dir("jsonl");
+        let (solver_data, full, partial) = boards();
+        let store = ResultStore::open(&dir, OutputFormat::Jsonl).unwrap();
+        for board in [&full, &partial, &full] {
+            store.save(&solver_data, board, 0, 1).unwrap();
+        }
+        let text = fs::read_to_string(dir.join("boards.jsonl")).unwrap();
+        assert_eq!(text.lines().count(), 2);
+        assert!(store.index().boards.values().all(|x| x.file.is_none()));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_torn_last_line_is_dropped() {
+        let dir = temp_dir("torn");
+        let (solver_data, full, partial) = boards();
+        let store = ResultStore::open(&dir, OutputFormat::Text).unwrap();
+        store.save(&solver_data, &full, 0, 1).unwrap();
+        let index = store.index();
+        drop(store);
+
+        let log_path = dir.join("finds.jsonl");
+        let logged = fs::read_to_string(&log_path).unwrap();
+        fs::write(&log_path, format!("{logged}{{\"hash\":\"ab")).unwrap();
+        let store = ResultStore::open(&dir, OutputFormat::Text).unwrap();
+        assert_eq!(store.index(), index);
+        assert_eq!(fs::read_to_string(&log_path).unwrap(), logged);
+
+        // Finds after the torn line are logged cleanly.
+        store.save(&solver_data, &partial, 0, 1).unwrap();
+        let index = store.index();
+        drop(store);
+        assert_eq!(
+            ResultStore::open(&dir, OutputFormat::Text).unwrap().index(),
+            index
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_corrupt_line_before_the_last_is_an_error() {
+        let dir = temp_dir("corrupt");
+        let (solver_data, full, _) = boards();
+        let store = ResultStore::open(&dir, OutputFormat::Text).unwrap();
+        store.save(&solver_data, &full, 0, 1).unwrap();
+        drop(store);
+
+        let log_path = dir.join("finds.jsonl");
+        let logged = fs::read_to_string(&log_path).unwrap();
+        fs::write(&log_path, format!("not json\n{logged}")).unwrap();
+        let error = ResultStore::open(&dir, OutputFormat::Text).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().ends_with("line 1 is corrupt"), "{error}");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_store_is_used_by_one_run_at_a_time() {
+        let dir = temp_dir("lock");
+        let store = ResultStore::open(&dir, OutputFormat::Text).unwrap();
+        let error = ResultStore::open(&dir, OutputFormat::Text).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::WouldBlock);
+        drop(store);
+        assert!(ResultStore::open(&dir, OutputFormat::Text).is_ok());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
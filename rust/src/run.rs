@@ -0,0 +1,265 @@
+//! What the long runs share: their settings, the flag that stops them, the sinks they send
+//! boards to, their progress reports and the report they end with.
+
+use crate::checkpoint::Checkpoint;
+use crate::config::SolverConfig;
+use crate::metrics::serve_metrics;
+use crate::progress::Progress;
+use crate::puzzle::Puzzle;
+use crate::result_store::ResultStore;
+use crate::sinks::{JsonlSink, ResultSink, SinkSpec, StdoutSink, WebhookSink};
+use crate::structs::{BoardScore, RotatedPiece};
+use crate::util::format_board;
+use log::{error, info, warn};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use thousands::Separable;
+
+/// How many of the deepest levels of the histogram each progress report shows.
+const PROGRESS_DEPTHS: usize = 16;
+
+/// Set to ask every search to stop at its next check, so that the run can report and checkpoint
+/// before it exits.
+pub static STOP: AtomicBool = AtomicBool::new(false);
+
+/// Settings after merging the flags, the chosen profile, the config file and the defaults.
+pub struct Settings {
+    pub puzzle: Puzzle,
+    pub config: SolverConfig,
+    pub threads: usize,
+    /// Searches in each repeat of a loop.
+    pub slots: usize,
+    /// Where to accept worker processes, if anywhere.
+    pub listen: Option<String>,
+    pub seed: u64,
+    pub checkpoint: Option<PathBuf>,
+    pub max_node_count: u64,
+    /// Seconds between progress reports, or 0 for none.
+    pub progress_interval: u64,
+    /// Where to serve metrics, if anywhere.
+    pub metrics_addr: Option<String>,
+    pub sinks: Vec<SinkSpec>,
+    /// Every setting above written out as a config file, which repeats the run when passed back
+    /// with `--config`.
+    pub record: String,
+}
+
+/// Names the settings' profile after a hash of their record, without writing it anywhere, so
+/// that runs with the same settings share a profile.
+pub fn name_settings(settings: &mut Settings) {
+    let puzzle = &settings.puzzle;
+    info!(
+        "Board is {}x{} with {} colours",
+        puzzle.width, puzzle.height, puzzle.num_colours
+    );
+
+    let hash = format!("{:x}", md5::compute(&settings.record));
+    settings.config.profile = Some(format!("profile_{}.toml", &hash[..12]));
+}
+
+/// Records the settings in the output directory and names the record in the config, so that
+/// every saved board can be traced back to the settings that produced it.
+pub fn record_settings(settings: &mut Settings) {
+    name_settings(settings);
+    let Some(filename) = settings.config.profile.take() else {
+        return;
+    };
+    let output_dir = &settings.config.output_dir;
+    match fs::create_dir_all(output_dir)
+        .and_then(|_| fs::write(output_dir.join(&filename), &settings.record))
+    {
+        Ok(()) => {
+            info!(
+                "Settings recorded in {}",
+                output_dir.join(&filename).display()
+            );
+            settings.config.profile = Some(filename);
+        }
+        Err(e) => warn!("Failed to record settings in {}: {e}", output_dir.display()),
+    }
+}
+
+/// The deepest board any worker has reached, and where it came from.
+pub struct BestBoard {
+    pub board: Vec<RotatedPiece>,
+    pub score: BoardScore,
+    pub depth: usize,
+    pub core: usize,
+    /// Which search found it, such as `loop 3, slot 01, repeat 2`.
+    pub search: String,
+}
+
+/// Opens a checkpoint directory.
+pub fn open_checkpoint(dir: &Path) -> Result<Arc<Checkpoint>, String> {
+    let checkpoint = Checkpoint::new(dir)
+        .map_err(|e| format!("Failed to open checkpoint directory {}: {e}", dir.display()))?;
+    info!("Checkpointing to {}", dir.display());
+    Ok(Arc::new(checkpoint))
+}
+
+/// The sinks that deep boards are sent to, and the result store if one of them is the output
+/// directory.
+pub struct Sinks {
+    pub all: Arc<Vec<Box<dyn ResultSink>>>,
+    pub store: Option<Arc<ResultStore>>,
+}
+
+/// Opens every configured sink.
+pub fn open_sinks(settings: &Settings) -> Result<Sinks, String> {
+    let config = &settings.config;
+    let mut all: Vec<Box<dyn ResultSink>> = vec![];
+    let mut store = None;
+    for spec in &settings.sinks {
+        let sink: Result<Box<dyn ResultSink>, String> = match spec {
+            SinkSpec::Directory => ResultStore::open(&config.output_dir, config.output_format)
+                .map(|x| {
+                    let x = Arc::new(x);
+                    store = Some(Arc::clone(&x));
+                    Box::new(x) as Box<dyn ResultSink>
+                })
+                .map_err(|e| e.to_string()),
+            SinkSpec::Stdout => Ok(Box::new(StdoutSink)),
+            SinkSpec::Jsonl(path) => JsonlSink::open(path)
+                .map(|x| Box::new(x) as Box<dyn ResultSink>)
+                .map_err(|e| e.to_string()),
+            SinkSpec::Webhook(url) => {
+                WebhookSink::new(url).map(|x| Box::new(x) as Box<dyn ResultSink>)
+            }
+        };
+        all.push(sink.map_err(|e| format!("Failed to open sink {spec}: {e}"))?);
+    }
+    info!(
+        "Sending boards to {}",
+        settings
+            .sinks
+            .iter()
+            .map(|x| match x {
+                SinkSpec::Directory => config.output_dir.display().to_string(),
+                x => x.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    Ok(Sinks {
+        all: Arc::new(all),
+        store,
+    })
+}
+
+/// Flushes every sink and reports what the result store holds.
+pub fn flush_sinks(sinks: &Sinks) {
+    if let Err(e) = sinks.all.flush() {
+        error!("Failed to flush results: {e}");
+    }
+    let Some(store) = &sinks.store else {
+        return;
+    };
+    let index = store.index();
+    info!(
+        "Result store holds {} distinct boards found {} times",
+        index.boards.len().separate_with_commas(),
+        index
+            .boards
+            .values()
+            .map(|x| x.count)
+            .sum::<u64>()
+            .separate_with_commas()
+    );
+}
+
+/// Starts the progress reports and the metrics endpoint, if they are wanted.
+pub fn watch_progress(progress: &Arc<Progress>, settings: &Settings) -> Result<(), String> {
+    if let Some(addr) = &settings.metrics_addr {
+        let addr = serve_metrics(addr, Arc::clone(progress))
+            .map_err(|e| format!("Failed to serve metrics at {addr}: {e}"))?;
+        info!("Serving metrics at http://{addr}/metrics");
+    }
+    if settings.progress_interval > 0 {
+        spawn_reporter(Arc::clone(progress), settings.progress_interval);
+    }
+    Ok(())
+}
+
+/// Reports the nodes per second, each worker's depth and the deepest levels of the histogram
+/// every `interval` seconds until the process exits.
+pub fn spawn_reporter(progress: Arc<Progress>, interval: u64) {
+    std::thread::spawn(move || {
+        let mut last_nodes = 0;
+        let mut last_report = Instant::now();
+        loop {
+            std::thread::sleep(Duration::from_secs(interval));
+            let snapshot = progress.snapshot();
+            let nodes = snapshot.nodes();
+            let seconds = last_report.elapsed().as_secs_f64();
+            last_report = Instant::now();
+            info!(
+                "Progress: {} nodes, {} per second",
+                nodes.separate_with_commas(),
+                (((nodes - last_nodes) as f64 / seconds) as u64).separate_with_commas()
+            );
+            last_nodes = nodes;
+
+            for (core, worker) in snapshot.workers.iter().enumerate() {
+                info!(
+                    "Core {core:02}: depth {}, best depth {}, {} nodes",
+                    worker.depth,
+                    worker.best_depth,
+                    worker.nodes.separate_with_commas()
+                );
+            }
+            let mut deepest: Vec<String> = snapshot
+                .solve_index_counts
+                .iter()
+                .enumerate()
+                .rev()
+                .filter(|x| *x.1 != 0)
+                .take(PROGRESS_DEPTHS)
+                .map(|(i, count)| format!("{i}: {}", count.separate_with_commas()))
+                .collect();
+            deepest.reverse();
+            info!("Nodes by depth: {}", deepest.join(", "));
+        }
+    });
+}
+
+/// Prints how many nodes were visited at each depth, the totals and the best board.
+pub fn report(
+    puzzle: &Puzzle,
+    index_counts: &[u64],
+    max_depth: usize,
+    best_board: Option<&BestBoard>,
+    overall_stopwatch: &Instant,
+) {
+    // This will only print valid numbers if you let the solver count how far you are.
+    for (i, count) in index_counts.iter().enumerate() {
+        if *count != 0 {
+            println!("{i} {}", count.separate_with_commas());
+        }
+    }
+    let total_index_count: u64 = index_counts.iter().sum();
+    let elapsed_time_seconds = overall_stopwatch.elapsed().as_secs();
+    let rate = total_index_count / elapsed_time_seconds.max(1);
+    info!(
+        "Total {} nodes in {} seconds, {} per second, max depth {}",
+        total_index_count.separate_with_commas(),
+        elapsed_time_seconds.separate_with_commas(),
+        rate.separate_with_commas(),
+        max_depth
+    );
+
+    if let Some(best) = best_board {
+        info!(
+            "Best board: depth {}, {} pieces, {} breaks, {} matched edges, from core {:02}, {}",
+            best.depth,
+            best.score.placed,
+            best.score.breaks,
+            best.score.matched_edges,
+            best.core,
+            best.search
+        );
+        println!("{}", format_board(puzzle, &best.board));
+    }
+}
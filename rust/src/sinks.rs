@@ -0,0 +1,512 @@
+use crate::result_store::ResultStore;
+use crate::solver_data::SolverData;
+use crate::structs::RotatedPiece;
+use crate::util::{board_record, board_text};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A deep board as the search found it.
+pub struct FoundBoard<'a> {
+    pub solver_data: &'a SolverData,
+    pub board: &'a [RotatedPiece],
+    pub worker: usize,
+    pub search_seed: u64,
+}
+
+/// Somewhere the search sends every board at least `min_solve_index_to_save` deep.
+pub trait ResultSink: Send + Sync {
+    fn send(&self, found: &FoundBoard) -> io::Result<()>;
+
+    /// Finishes any buffered work. Called at the end of each loop.
+    fn flush(&self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Sends each board to every sink in turn, even if an earlier one fails. The first failure is
+/// returned.
+impl ResultSink for Vec<Box<dyn ResultSink>> {
+    fn send(&self, found: &FoundBoard) -> io::Result<()> {
+        let results: Vec<_> = self.iter().map(|x| x.send(found)).collect();
+        results.into_iter().collect()
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        let results: Vec<_> = self.iter().map(|x| x.flush()).collect();
+        results.into_iter().collect()
+    }
+}
+
+impl<T: ResultSink + ?Sized> ResultSink for Arc<T> {
+    fn send(&self, found: &FoundBoard) -> io::Result<()> {
+        (**self).send(found)
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        (**self).flush()
+    }
+}
+
+/// The directory sink: boards go to the deduplicating store in the output directory.
+impl ResultSink for ResultStore {
+    fn send(&self, found: &FoundBoard) -> io::Result<()> {
+        self.save(
+            found.solver_data,
+            found.board,
+            found.worker,
+            found.search_seed,
+        )
+        .map(|_| ())
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        ResultStore::flush(self)
+    }
+}
+
+/// Prints each board to standard output in the text format.
+pub struct StdoutSink;
+
+impl ResultSink for StdoutSink {
+    fn send(&self, found: &FoundBoard) -> io::Result<()> {
+        let depth = found.board.iter().filter(|x| x.reid > 0).count();
+        let text = board_text(found.solver_data, found.board, found.search_seed);
+        // One write so boards from different workers do not interleave.
+        let mut stdout = io::stdout().lock();
+        write!(
+            stdout,
+            "Worker {:02} found a board {depth} deep:\n{text}\n",
+            found.worker
+        )?;
+        stdout.flush()
+    }
+}
+
+/// Appends every board, including repeats, to a JSON Lines file.
+pub struct JsonlSink {
+    file: Mutex<File>,
+}
+
+impl JsonlSink {
+    pub fn open(path: &Path) -> io::Result<JsonlSink> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(JsonlSink {
+            file: Mutex::new(file),
+        })
+    }
+}
+
+impl ResultSink for JsonlSink {
+    fn send(&self, found: &FoundBoard) -> io::Result<()> {
+        let record = board_record(
+            found.solver_data,
+            found.board,
+            found.worker,
+            found.search_seed,
+        );
+        let line = serde_json::to_string(&record)? + "\n";
+        self.file.lock().unwrap().write_all(line.as_bytes())
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        self.file.lock().unwrap().sync_data()
+    }
+}
+
+/// A plain `http://` endpoint that boards are posted to.
+struct Webhook {
+    host: String,
+    port: u16,
+    path: String,
+}
+
+impl Webhook {
+    fn parse(url: &str) -> Result<Webhook, String> {
+        let rest = url
+            .strip_prefix("http://")
+            .ok_or_else(|| format!("webhook {url:?} must start with http://"))?;
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (
+                host,
+                port.parse()
+                    .map_err(|e| format!("webhook {url:?} has an invalid port: {e}"))?,
+            ),
+            None => (authority, 80),
+        };
+        if host.is_empty() {
+            return Err(format!("webhook {url:?} has no host"));
+        }
+        Ok(Webhook {
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+        })
+    }
+
+    /// Connects to the first of the host's addresses that answers within `WEBHOOK_TIMEOUT`.
+    fn connect(&self) -> io::Result<TcpStream> {
+        let mut last_error = None;
+        for addr in (self.host.as_str(), self.port).to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, WEBHOOK_TIMEOUT) {
+                Ok(stream) => return Ok(stream),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| {
+            io::Error::other(format!("webhook host {} has no addresses", self.host))
+        }))
+    }
+
+    /// Posts one JSON body. Any response other than 2xx counts as a failure.
+    fn post(&self, body: &str) -> io::Result<()> {
+        let request = format!(
+            "POST {} HTTP/1.1\r\nHost: {}:{}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            self.path,
+            self.host,
+            self.port,
+            body.len()
+        );
+
+        let mut stream = self.connect()?;
+        stream.set_read_timeout(Some(WEBHOOK_TIMEOUT))?;
+        stream.set_write_timeout(Some(WEBHOOK_TIMEOUT))?;
+        stream.write_all(request.as_bytes())?;
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response)?;
+        let status = response.split_whitespace().nth(1).unwrap_or_default();
+        if status.starts_with('2') {
+            Ok(())
+        } else {
+            Err(io::Error::other(format!(
+                "webhook {}:{}{} answered {:?}",
+                self.host,
+                self.port,
+                self.path,
+                response.lines().next().unwrap_or_default()
+            )))
+        }
+    }
+}
+
+/// What the search hands the thread that posts to a webhook.
+enum WebhookMessage {
+    Post(String),
+    /// Asks for an answer once every earlier post has been made.
+    Flush(Sender<io::Result<()>>),
+}
+
+/// Posts each board as JSON to a plain `http://` endpoint, such as a local service that passes
+/// boards on to a chat channel. The posts are made by a thread of the sink's own, so a slow or
+/// unreachable endpoint never holds up the search. A post that fails is reported by the next
+/// flush.
+pub struct WebhookSink {
+    posts: Sender<WebhookMessage>,
+}
+
+impl WebhookSink {
+    pub fn new(url: &str) -> Result<WebhookSink, String> {
+        let webhook = Webhook::parse(url)?;
+        let (posts, messages) = mpsc::channel();
+        thread::spawn(move || post_boards(&webhook, messages));
+        Ok(WebhookSink { posts })
+    }
+
+    fn queue(&self, message: WebhookMessage) -> io::Result<()> {
+        self.posts
+            .send(message)
+            .map_err(|_| io::Error::other("the webhook thread has stopped"))
+    }
+}
+
+/// Posts boards in the order they were sent until the sink is dropped, answering each flush with
+/// the posts that failed since the one before.
+fn post_boards(webhook: &Webhook, messages: Receiver<WebhookMessage>) {
+    let mut failed = 0;
+    let mut first_failure = None;
+    for message in messages {
+        match message {
+            WebhookMessage::Post(body) => {
+                if let Err(e) = webhook.post(&body) {
+                    failed += 1;
+                    first_failure.get_or_insert(e);
+                }
+            }
+            WebhookMessage::Flush(answer) => {
+                let result = match first_failure.take() {
+                    None => Ok(()),
+                    Some(e) => Err(io::Error::other(format!(
+                        "{failed} boards failed to post, the first with: {e}"
+                    ))),
+                };
+                failed = 0;
+                let _ = answer.send(result);
+            }
+        }
+    }
+}
+
+impl ResultSink for WebhookSink {
+    fn send(&self, found: &FoundBoard) -> io::Result<()> {
+        let record = board_record(
+            found.solver_data,
+            found.board,
+            found.worker,
+            found.search_seed,
+        );
+        self.queue(WebhookMessage::Post(serde_json::to_string(&record)?))
+    }
+
+    /// Waits until every board sent so far has been posted.
+    fn flush(&self) -> io::Result<()> {
+        let (answer, result) = mpsc::channel();
+        self.queue(WebhookMessage::Flush(answer))?;
+        result
+            .recv()
+            .map_err(|_| io::Error::other("the webhook thread has stopped"))?
+    }
+}
+
+/// A sink as it is named in the settings: `directory`, `stdout`, `jsonl=<path>` or
+/// `webhook=<url>`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum SinkSpec {
+    Directory,
+    Stdout,
+    Jsonl(PathBuf),
+    Webhook(String),
+}
+
+impl FromStr for SinkSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<SinkSpec, String> {
+        match s.split_once('=') {
+            None if s == "directory" => Ok(SinkSpec::Directory),
+            None if s == "stdout" => Ok(SinkSpec::Stdout),
+            Some(("jsonl", path)) => Ok(SinkSpec::Jsonl(PathBuf::from(path))),
+            Some(("webhook", url)) => {
+                Webhook::parse(url)?;
+                Ok(SinkSpec::Webhook(url.to_string()))
+            }
+            _ => Err(format!(
+                "unknown sink {s:?}, expected directory, stdout, jsonl=<path> or webhook=<url>"
+            )),
+        }
+    }
+}
+
+impl TryFrom<String> for SinkSpec {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<SinkSpec, String> {
+        s.parse()
+    }
+}
+
+impl From<SinkSpec> for String {
+    fn from(spec: SinkSpec) -> String {
+        spec.to_string()
+    }
+}
+
+impl fmt::Display for SinkSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SinkSpec::Directory => write!(f, "directory"),
+            SinkSpec::Stdout => write!(f, "stdout"),
+            SinkSpec::Jsonl(path) => write!(f, "jsonl={}", path.display()),
+            SinkSpec::Webhook(url) => write!(f, "webhook={url}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SolverConfig;
+    use crate::generate::generate_puzzle;
+    use crate::solver_data::prepare_pieces_and_heuristics;
+    use crate::structs::BoardRecord;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+
+    fn solved() -> (SolverData, Vec<RotatedPiece>) {
+        let generated = generate_puzzle(4, 4, 3, 4, 2).unwrap();
+        let config = SolverConfig::for_board(4, 4);
+        let solver_data = prepare_pieces_and_heuristics(&generated.puzzle, &config, 1);
+        (solver_data, generated.solution)
+    }
+
+    /// Answers one request with `status`, returning its request line, headers and body.
+    fn serve_once(listener: TcpListener, status: &'static str) -> thread::JoinHandle<Vec<String>> {
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut lines = vec![];
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line == "\r\n" {
+                    break;
+                }
+                lines.push(line.trim_end().to_string());
+            }
+            let length: usize = lines
+                .iter()
+                .find_map(|x| x.strip_prefix("Content-Length: "))
+                .unwrap()
+                .parse()
+                .unwrap();
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            lines.push(String::from_utf8(body).unwrap());
+            write!(
+                reader.get_mut(),
+                "HTTP/1.1 {status}\r\nContent-Length: 0\r\n\r\n"
+            )
+            .unwrap();
+            lines
+        })
+    }
+
+    #[test]
+    fn json_lines_get_every_board() {
+        let dir =
+            std::env::temp_dir().join(format!("eternity-solver-sinks-{}", std::process::id()));
+        let path = dir.join("nested").join("boards.jsonl");
+        let (solver_data, board) = solved();
+        let sink = JsonlSink::open(&path).unwrap();
+        for worker in [3, 4] {
+            let found = FoundBoard {
+                solver_data: &solver_data,
+                board: &board,
+                worker,
+                search_seed: 9,
+            };
+            sink.send(&found).unwrap();
+        }
+        sink.flush().unwrap();
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        let records: Vec<BoardRecord> = text
+            .lines()
+            .map(|x| serde_json::from_str(x).unwrap())
+            .collect();
+        let workers: Vec<_> = records.iter().map(|x| (x.worker, x.depth)).collect();
+        assert_eq!(workers, [(3, 16), (4, 16)]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn webhooks_post_one_board_per_request() {
+        let (solver_data, board) = solved();
+        let found = FoundBoard {
+            solver_data: &solver_data,
+            board: &board,
+            worker: 2,
+            search_seed: 9,
+        };
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = serve_once(listener, "204 No Content");
+        let sink = WebhookSink::new(&format!("http://127.0.0.1:{port}/boards")).unwrap();
+        sink.send(&found).unwrap();
+        sink.flush().unwrap();
+
+        let request = server.join().unwrap();
+        assert_eq!(request[0], "POST /boards HTTP/1.1");
+        assert!(request.contains(&format!("Host: 127.0.0.1:{port}")));
+        assert!(request.contains(&"Content-Type: application/json".to_string()));
+        let record: BoardRecord = serde_json::from_str(request.last().unwrap()).unwrap();
+        assert_eq!(
+            (record.worker, record.search_seed, record.depth),
+            (2, 9, 16)
+        );
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = serve_once(listener, "500 Internal Server Error");
+        let sink = WebhookSink::new(&format!("http://127.0.0.1:{port}")).unwrap();
+        sink.send(&found).unwrap();
+        let error = sink.flush().unwrap_err();
+        assert!(
+            error.to_string().contains("500 Internal Server Error"),
+            "{error}"
+        );
+        assert_eq!(server.join().unwrap()[0], "POST / HTTP/1.1");
+        sink.flush().unwrap();
+    }
+
+    #[test]
+    fn webhooks_post_without_holding_up_the_search() {
+        let (solver_data, board) = solved();
+        let found = FoundBoard {
+            solver_data: &solver_data,
+            board: &board,
+            worker: 2,
+            search_seed: 9,
+        };
+
+        // Nothing answers until the board has been sent.
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let sink = WebhookSink::new(&format!("http://127.0.0.1:{port}")).unwrap();
+        sink.send(&found).unwrap();
+        let server = serve_once(listener, "200 OK");
+        sink.flush().unwrap();
+        assert_eq!(server.join().unwrap()[0], "POST / HTTP/1.1");
+    }
+
+    #[test]
+    fn webhook_urls_need_a_host_and_plain_http() {
+        let webhook = Webhook::parse("http://example.com").unwrap();
+        assert_eq!(
+            (webhook.host.as_str(), webhook.port, webhook.path.as_str()),
+            ("example.com", 80, "/")
+        );
+        let webhook = Webhook::parse("http://localhost:8080/a/b").unwrap();
+        assert_eq!(
+            (webhook.host.as_str(), webhook.port, webhook.path.as_str()),
+            ("localhost", 8080, "/a/b")
+        );
+
+        assert!(Webhook::parse("https://example.com").is_err());
+        assert!(Webhook::parse("http://:80/").is_err());
+        assert!(Webhook::parse("http://example.com:http/").is_err());
+    }
+
+    #[test]
+    fn sink_specs_read_back_as_written() {
+        for text in [
+            "directory",
+            "stdout",
+            "jsonl=out/boards.jsonl",
+            "webhook=http://localhost:9/",
+        ] {
+            assert_eq!(text.parse::<SinkSpec>().unwrap().to_string(), text);
+        }
+        assert!("webhook=ftp://localhost".parse::<SinkSpec>().is_err());
+        assert!("files".parse::<SinkSpec>().is_err());
+    }
+}
@@ -0,0 +1,130 @@
+use crate::puzzle::Puzzle;
+use crate::sinks::{FoundBoard, ResultSink};
+use crate::structs::RotatedPiece;
+use std::collections::HashSet;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Turns a full board a quarter turn clockwise, turning every piece with it. The result is
+/// `height` wide and `width` tall.
+pub fn rotate_board(puzzle: &Puzzle, board: &[RotatedPiece]) -> Vec<RotatedPiece> {
+    let (width, height) = (puzzle.width, puzzle.height);
+    let mut rotated = vec![RotatedPiece::default(); board.len()];
+    for row in 0..height {
+        for col in 0..width {
+            let piece = board[row * width + col];
+            let rotations = (piece.rotations + 1) % 4;
+            let sides = puzzle.placed_piece(&RotatedPiece { rotations, ..piece });
+            // The bottom left corner moves to the top left, and so on round the board.
+            rotated[(width - 1 - col) * height + row] = RotatedPiece {
+                rotations,
+                top: sides.map_or(0, |x| x.top),
+                right: sides.map_or(0, |x| x.right),
+                ..piece
+            };
+        }
+    }
+    rotated
+}
+
+/// How many ways a board of the puzzle can be turned round and still fit: four on a square
+/// board, two on any other, and one when there is a clue, since turning the board would move it.
+pub fn num_symmetries(puzzle: &Puzzle) -> usize {
+    match puzzle.start_piece {
+        Some(_) => 1,
+        None if puzzle.width == puzzle.height => 4,
+        None => 2,
+    }
+}
+
+/// The boards that are the same solution as `board` turned round, starting with `board` itself,
+/// one for each of `num_symmetries`.
+pub fn board_symmetries(puzzle: &Puzzle, board: &[RotatedPiece]) -> Vec<Vec<RotatedPiece>> {
+    if num_symmetries(puzzle) == 1 {
+        return vec![board.to_vec()];
+    }
+    // A quarter turn swaps the sides, so the next is taken with them swapped.
+    let swapped = Puzzle {
+        width: puzzle.height,
+        height: puzzle.width,
+        ..puzzle.clone()
+    };
+    let quarter = rotate_board(puzzle, board);
+    let half = rotate_board(&swapped, &quarter);
+    if num_symmetries(puzzle) == 2 {
+        return vec![board.to_vec(), half];
+    }
+    let three_quarters = rotate_board(puzzle, &half);
+    vec![board.to_vec(), quarter, half, three_quarters]
+}
+
+/// The board that stands for all of `board`'s symmetries: the one whose pieces and rotations,
+/// read in board order, come first.
+pub fn canonical_board(puzzle: &Puzzle, board: &[RotatedPiece]) -> Vec<RotatedPiece> {
+    board_symmetries(puzzle, board)
+        .into_iter()
+        .min_by_key(|x| board_key(x))
+        .unwrap()
+}
+
+fn board_key(board: &[RotatedPiece]) -> Vec<(u16, u8)> {
+    board.iter().map(|x| (x.reid, x.rotations)).collect()
+}
+
+/// A sink that counts the full boards it is sent, and passes each distinct one, in its
+/// canonical orientation, on to `output` if there is one. Partial boards are ignored.
+pub struct SolutionCollector {
+    puzzle: Puzzle,
+    output: Option<Box<dyn ResultSink>>,
+    found: AtomicU64,
+    distinct: Mutex<HashSet<Vec<(u16, u8)>>>,
+}
+
+impl SolutionCollector {
+    pub fn new(puzzle: &Puzzle, output: Option<Box<dyn ResultSink>>) -> SolutionCollector {
+        SolutionCollector {
+            puzzle: puzzle.clone(),
+            output,
+            found: AtomicU64::new(0),
+            distinct: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// How many full boards have been sent, counting each symmetry separately.
+    pub fn found(&self) -> u64 {
+        self.found.load(Ordering::Relaxed)
+    }
+
+    /// How many full boards have been sent that are not symmetries of each other.
+    pub fn distinct(&self) -> usize {
+        self.distinct.lock().unwrap().len()
+    }
+}
+
+impl ResultSink for SolutionCollector {
+    fn send(&self, found: &FoundBoard) -> io::Result<()> {
+        if found.board.iter().any(|x| x.reid == 0) {
+            return Ok(());
+        }
+        self.found.fetch_add(1, Ordering::Relaxed);
+        let canonical = canonical_board(&self.puzzle, found.board);
+        if !self.distinct.lock().unwrap().insert(board_key(&canonical)) {
+            return Ok(());
+        }
+        match &self.output {
+            Some(output) => output.send(&FoundBoard {
+                board: &canonical,
+                ..*found
+            }),
+            None => Ok(()),
+        }
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        match &self.output {
+            Some(output) => output.flush(),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,293 @@
+//! Random runs, which search loop after loop of seeded searches until they are stopped, on
+//! local threads and on any worker processes that join.
+
+use crate::checkpoint::{LoopCheckpoint, RunCheckpoint};
+use crate::pool::{
+    spawn_feeder, spawn_workers, FeederPlan, JobQueue, JobResult, ResumedLoop, WorkerContext,
+    REPEATS,
+};
+use crate::progress::Progress;
+use crate::remote::{serve_workers, CoordinatorContext};
+use crate::run::{
+    flush_sinks, open_checkpoint, open_sinks, report, watch_progress, BestBoard, Settings,
+};
+use log::{info, warn};
+use std::collections::BTreeMap;
+use std::net::TcpListener;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::time::Instant;
+use thousands::Separable;
+
+/// Searches until the process is stopped.
+pub fn run_solve(settings: Settings) -> Result<(), String> {
+    if settings.slots == 0 {
+        return Err(
+            "A loop needs at least one slot, so set --slots when running without threads".into(),
+        );
+    }
+    if settings.threads == 0 && settings.listen.is_none() {
+        return Err(
+            "Nothing would search without threads, unless --listen lets workers join".into(),
+        );
+    }
+    let sinks = open_sinks(&settings)?;
+    let progress = Arc::new(Progress::new(settings.threads, settings.puzzle.num_cells()));
+    watch_progress(&progress, &settings)?;
+    let num_virtual_cores = settings.threads;
+    let listener = match settings.listen.as_deref() {
+        Some(addr) => Some(
+            TcpListener::bind(addr)
+                .map_err(|e| format!("Failed to listen for workers at {addr}: {e}"))?,
+        ),
+        None => None,
+    };
+    let puzzle = settings.puzzle;
+    let num_cells = puzzle.num_cells();
+    let config = settings.config;
+    let checkpoint = settings
+        .checkpoint
+        .as_deref()
+        .map(open_checkpoint)
+        .transpose()?;
+    let master_seed = settings.seed;
+    info!("Master seed {master_seed}");
+    let overall_stopwatch = Instant::now();
+    let mut run = RunCheckpoint {
+        loop_count: 0,
+        index_counts: vec![0u64; num_cells + 1],
+        max_depth: 0,
+    };
+    // The loops that have been started but not finished, with the totals of their finished
+    // searches.
+    let mut loops: BTreeMap<u64, LoopCheckpoint> = BTreeMap::new();
+    let mut resumed = vec![];
+
+    if let Some(checkpoint) = &checkpoint {
+        match checkpoint.load_run() {
+            Ok(Some(loaded)) if loaded.index_counts.len() == num_cells + 1 => {
+                info!("Resuming after loop {}", loaded.loop_count);
+                run = loaded;
+            }
+            Ok(Some(_)) => warn!("Ignoring checkpoint for a different board size"),
+            Ok(None) => {}
+            Err(e) => warn!("Failed to read run checkpoint: {e}"),
+        }
+        match checkpoint.loops() {
+            Ok(started) => {
+                for loop_checkpoint in started {
+                    let loop_count = loop_checkpoint.loop_count;
+                    if loop_count <= run.loop_count
+                        || loop_checkpoint.index_counts.len() != num_cells + 1
+                    {
+                        continue;
+                    }
+                    match checkpoint.load_solver_data(loop_count) {
+                        Ok(Some(solver_data))
+                            if solver_data.puzzle == puzzle && solver_data.config == config =>
+                        {
+                            info!(
+                                "Resuming loop {loop_count} with {} searches finished",
+                                loop_checkpoint.finished.len()
+                            );
+                            resumed.push(ResumedLoop {
+                                loop_count,
+                                slots: loop_checkpoint.slots,
+                                solver_data: Arc::new(solver_data),
+                                finished: loop_checkpoint.finished.clone(),
+                            });
+                            loops.insert(loop_count, loop_checkpoint);
+                        }
+                        Ok(Some(_)) => {
+                            warn!("Ignoring checkpoint of loop {loop_count} for a different puzzle or config")
+                        }
+                        Ok(None) => {}
+                        Err(e) => warn!("Failed to read solver data checkpoint: {e}"),
+                    }
+                }
+            }
+            Err(e) => warn!("Failed to read loop checkpoints: {e}"),
+        }
+    }
+
+    // Everything counted so far, for the reports.
+    let mut index_counts = run.index_counts.clone();
+    let mut max_depth = run.max_depth;
+    for loop_checkpoint in loops.values() {
+        for (total, count) in index_counts.iter_mut().zip(&loop_checkpoint.index_counts) {
+            *total += count;
+        }
+        max_depth = max_depth.max(loop_checkpoint.max_depth);
+    }
+    let mut best_board: Option<BestBoard> = None;
+
+    let (job_sender, jobs) = mpsc::sync_channel(0);
+    let (result_sender, results) = mpsc::channel();
+    spawn_feeder(
+        FeederPlan {
+            puzzle: puzzle.clone(),
+            config: config.clone(),
+            master_seed,
+            slots: settings.slots,
+            next_loop: loops.keys().last().unwrap_or(&run.loop_count) + 1,
+            resumed,
+            checkpoint: checkpoint.clone(),
+        },
+        job_sender,
+    );
+    let jobs = Arc::new(JobQueue::new(jobs));
+    if let Some(listener) = listener {
+        let context = CoordinatorContext {
+            puzzle: puzzle.clone(),
+            config: config.clone(),
+            max_node_count: settings.max_node_count,
+            sinks: Arc::clone(&sinks.all),
+            progress: Arc::clone(&progress),
+            checkpoint: checkpoint.clone(),
+        };
+        let addr = listener.local_addr().map(|x| x.to_string());
+        serve_workers(listener, Arc::clone(&jobs), result_sender.clone(), context)
+            .map_err(|e| format!("Failed to listen for workers: {e}"))?;
+        info!(
+            "Listening for workers on {}",
+            addr.unwrap_or("an unknown address".to_string())
+        );
+    }
+    info!(
+        "Solving with {num_virtual_cores} cores and {} searches per repeat...",
+        settings.slots
+    );
+    spawn_workers(
+        num_virtual_cores,
+        jobs,
+        result_sender,
+        WorkerContext {
+            max_node_count: settings.max_node_count,
+            sinks: Arc::clone(&sinks.all),
+            progress,
+            checkpoint: checkpoint.clone(),
+        },
+    );
+
+    // The results stop coming once every worker has stopped.
+    for result in results {
+        let JobResult {
+            loop_count,
+            slots,
+            slot,
+            repeat,
+            worker,
+            solver_result,
+            seconds,
+        } = result;
+        for (total, count) in index_counts.iter_mut().zip(&solver_result.solve_indexes) {
+            *total += count;
+        }
+        max_depth = max_depth.max(solver_result.max_depth);
+        let is_better = best_board.as_ref().is_none_or(|best| {
+            (
+                solver_result.max_depth,
+                solver_result.best_score.matched_edges,
+            ) > (best.depth, best.score.matched_edges)
+        });
+        if is_better {
+            best_board = Some(BestBoard {
+                board: solver_result.best_board.clone(),
+                score: solver_result.best_score,
+                depth: solver_result.max_depth,
+                core: worker,
+                search: format!("loop {loop_count}, slot {slot:02}, repeat {repeat}"),
+            });
+        }
+
+        if solver_result.stopped {
+            info!("Core {worker:02}: stopped loop {loop_count}, slot {slot:02}, repeat {repeat}");
+            continue;
+        }
+        info!(
+            "Core {worker:02}: finish loop {loop_count}, slot {slot:02}, repeat {repeat}, best depth {} in {} seconds",
+            solver_result.max_depth,
+            seconds.separate_with_commas()
+        );
+
+        let loop_checkpoint = loops.entry(loop_count).or_insert_with(|| LoopCheckpoint {
+            loop_count,
+            slots,
+            finished: vec![],
+            index_counts: vec![0; num_cells + 1],
+            max_depth: 0,
+        });
+        loop_checkpoint.finished.push((slot, repeat));
+        for (total, count) in loop_checkpoint
+            .index_counts
+            .iter_mut()
+            .zip(&solver_result.solve_indexes)
+        {
+            *total += count;
+        }
+        loop_checkpoint.max_depth = loop_checkpoint.max_depth.max(solver_result.max_depth);
+        let loop_finished = loop_checkpoint.finished.len() == slots * REPEATS as usize;
+        if let Some(checkpoint) = &checkpoint {
+            let result = checkpoint
+                .save_loop(loop_checkpoint)
+                .and_then(|_| checkpoint.remove_job(loop_count, slot, repeat));
+            if let Err(e) = result {
+                warn!("Failed to write checkpoint for loop {loop_count}: {e}");
+            }
+        }
+        if !loop_finished {
+            continue;
+        }
+
+        // Loops can finish out of order, but the run only moves on past loops that have all
+        // finished.
+        while let Some(entry) = loops.first_entry() {
+            let loop_checkpoint = entry.get();
+            if loop_checkpoint.loop_count != run.loop_count + 1
+                || loop_checkpoint.finished.len() != loop_checkpoint.slots * REPEATS as usize
+            {
+                break;
+            }
+            let loop_checkpoint = entry.remove();
+            run.loop_count = loop_checkpoint.loop_count;
+            for (total, count) in run
+                .index_counts
+                .iter_mut()
+                .zip(&loop_checkpoint.index_counts)
+            {
+                *total += count;
+            }
+            run.max_depth = run.max_depth.max(loop_checkpoint.max_depth);
+            if let Some(checkpoint) = &checkpoint {
+                let result = checkpoint
+                    .save_run(&run)
+                    .and_then(|_| checkpoint.remove_loop(run.loop_count));
+                if let Err(e) = result {
+                    warn!("Failed to write run checkpoint: {e}");
+                }
+            }
+        }
+
+        info!("Result after loop {loop_count}");
+        flush_sinks(&sinks);
+        report(
+            &puzzle,
+            &index_counts,
+            max_depth,
+            best_board.as_ref(),
+            &overall_stopwatch,
+        );
+    }
+
+    info!("Result");
+    flush_sinks(&sinks);
+    report(
+        &puzzle,
+        &index_counts,
+        max_depth,
+        best_board.as_ref(),
+        &overall_stopwatch,
+    );
+    info!("Stopped");
+    Ok(())
+}
@@ -1,22 +1,207 @@
 use crate::bits::{clear_bit, is_clear, set_bit};
-use crate::config::{MAX_HEURISTIC_INDEX, MAX_NODE_COUNT, MIN_SOLVE_INDEX_TO_SAVE};
+use crate::config::{CHECKPOINT_NODE_INTERVAL, PROGRESS_NODE_INTERVAL};
+use crate::progress::WorkerProgress;
+use crate::sinks::{FoundBoard, ResultSink};
 use crate::solver_data::SolverData;
 use crate::structs::{RotatedPiece, SolverResult};
-use crate::util::save_board;
-use rand::Rng;
+use crate::util::score_board;
+use log::error;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
 
-pub fn solve_puzzle(solver_data: &SolverData) -> SolverResult {
-    let mut piece_used = [0u64; 5];
-    let mut cumulative_heuristic_side_count = [0u8; 256];
-    let mut piece_index_to_try_next = [0u8; 256];
-    let mut cumulative_breaks = [0u8; 256];
-    let mut solve_index_counts = [0u64; 257];
-    solve_index_counts[0] = 0; // Avoid warning when unused.
-    let mut board = [RotatedPiece::default(); 256];
+/// Everything a worker needs to carry on a search where it left off, including the randomised
+/// candidate order for the bottom row.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SearchState {
+    /// Which worker runs the search, recorded with the boards it saves.
+    #[serde(default)]
+    pub worker: usize,
+    pub seed: u64,
+    pub board: Vec<RotatedPiece>,
+    pub piece_used: Vec<u64>,
+    pub cumulative_heuristic_side_count: Vec<u8>,
+    /// Where in its candidate list each position carries on. Lists can be longer than 255.
+    pub piece_index_to_try_next: Vec<usize>,
+    pub cumulative_breaks: Vec<u8>,
+    pub solve_index_counts: Vec<u64>,
+    pub bottom_sides: Vec<Vec<RotatedPiece>>,
+    pub solve_index: usize,
+    pub max_solve_index: usize,
+    pub node_count: u64,
+    /// The board as it stood when `max_solve_index` was reached. Empty in checkpoints written
+    /// before it was recorded, in which case the current board stands in for it.
+    #[serde(default)]
+    pub best_board: Vec<RotatedPiece>,
+    /// How many placements are fixed. The search ends when it would have to take one back.
+    #[serde(default = "default_root_depth")]
+    pub root_depth: usize,
+    /// Whether the search carries on past full boards, so that it tries every placement below
+    /// the fixed ones.
+    #[serde(default)]
+    pub exhaustive: bool,
+}
+
+fn default_root_depth() -> usize {
+    1
+}
+
+impl SearchState {
+    /// Shuffles the bottom row candidates and places a random first corner, both driven by `seed`.
+    pub fn new(solver_data: &SolverData, worker: usize, seed: u64) -> SearchState {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let bottom_sides = bottom_sides(solver_data, || rng.random_range(0..99));
+        let corner_list = &solver_data.corners[0];
+        let first_corner_piece = corner_list[rng.random_range(0..corner_list.len())];
+
+        let mut state = SearchState::empty(solver_data, worker, seed, bottom_sides);
+        state.place(solver_data, first_corner_piece);
+        state.root_depth = 1;
+        state.max_solve_index = 1;
+        state.best_board.clone_from(&state.board);
+        state
+    }
+
+    /// Places `prefix`, the first pieces in the search order, for a search that tries every
+    /// placement below them. Bottom row candidates are tried in the solver data's order, so
+    /// the search is the same every time.
+    pub fn from_prefix(
+        solver_data: &SolverData,
+        worker: usize,
+        prefix: &[RotatedPiece],
+    ) -> SearchState {
+        let bottom_sides = bottom_sides(solver_data, || 0);
+        let mut state = SearchState::empty(solver_data, worker, 0, bottom_sides);
+        for piece in prefix {
+            state.place(solver_data, *piece);
+        }
+        state.root_depth = prefix.len();
+        state.max_solve_index = prefix.len();
+        state.best_board.clone_from(&state.board);
+        state.exhaustive = true;
+        state
+    }
+
+    fn empty(
+        solver_data: &SolverData,
+        worker: usize,
+        seed: u64,
+        bottom_sides: Vec<Vec<RotatedPiece>>,
+    ) -> SearchState {
+        let num_cells = solver_data.puzzle.num_cells();
+        SearchState {
+            worker,
+            seed,
+            board: vec![RotatedPiece::default(); num_cells],
+            piece_used: vec![0u64; (solver_data.puzzle.pieces.len() >> 6) + 1],
+            cumulative_heuristic_side_count: vec![0u8; num_cells],
+            piece_index_to_try_next: vec![0; num_cells],
+            cumulative_breaks: vec![0u8; num_cells],
+            solve_index_counts: vec![0u64; num_cells + 1],
+            bottom_sides,
+            solve_index: 0,
+            max_solve_index: 0,
+            node_count: 0,
+            best_board: vec![],
+            root_depth: 0,
+            exhaustive: false,
+        }
+    }
 
-    let mut rng = rand::rng();
+    /// The pieces the search would place next, in the order it would try them. Pieces that
+    /// would break the break or heuristic limits are left out, as are those already used.
+    pub fn candidates(&self, solver_data: &SolverData) -> Vec<RotatedPiece> {
+        let solve_index = self.solve_index;
+        if solve_index == 0 {
+            return solver_data.corners[0].clone();
+        }
+        if solve_index >= solver_data.puzzle.num_cells() {
+            return vec![];
+        }
+        let width = solver_data.puzzle.width;
+        let num_colours = solver_data.puzzle.num_colours;
+        let row = solver_data.board_search_sequence[solve_index].row as usize;
+        let col = solver_data.board_search_sequence[solve_index].column as usize;
+        let board = &self.board;
+        let candidates: &[RotatedPiece] = if row == 0 {
+            let key = (board[col - 1].right as usize) * num_colours;
+            if col < width - 1 {
+                &self.bottom_sides[key]
+            } else {
+                &solver_data.corners[key]
+            }
+        } else {
+            let left_side = if col == 0 {
+                0
+            } else {
+                board[row * width + (col - 1)].right
+            };
+            let key =
+                (left_side as usize) * num_colours + (board[(row - 1) * width + col].top as usize);
+            &solver_data.get_pieces(solver_data.master_piece_lookup[row * width + col])[key]
+        };
+
+        // The same checks, and the same early ends, as the search itself.
+        let breaks_this_turn =
+            solver_data.break_array[solve_index] - self.cumulative_breaks[solve_index - 1];
+        let mut fitting = vec![];
+        for piece in candidates {
+            if piece.breaks > breaks_this_turn {
+                break;
+            }
+            if !is_clear(&self.piece_used, piece.reid as usize) {
+                continue;
+            }
+            if solve_index <= solver_data.config.max_heuristic_index
+                && ((self.cumulative_heuristic_side_count[solve_index - 1]
+                    + piece.heuristic_side_count)
+                    < solver_data.heuristic_array[solve_index] as u8)
+            {
+                break;
+            }
+            fitting.push(*piece);
+        }
+        fitting
+    }
+
+    /// Places `piece` at the next position in the search order.
+    pub fn place(&mut self, solver_data: &SolverData, piece: RotatedPiece) {
+        let solve_index = self.solve_index;
+        let cell = &solver_data.board_search_sequence[solve_index];
+        self.board[cell.row as usize * solver_data.puzzle.width + cell.column as usize] = piece;
+        set_bit(&mut self.piece_used, piece.reid as usize);
+        let (breaks, heuristic_side_count) = match solve_index {
+            0 => (0, 0),
+            _ => (
+                self.cumulative_breaks[solve_index - 1],
+                self.cumulative_heuristic_side_count[solve_index - 1],
+            ),
+        };
+        self.cumulative_breaks[solve_index] = breaks + piece.breaks;
+        self.cumulative_heuristic_side_count[solve_index] =
+            heuristic_side_count + piece.heuristic_side_count;
+        self.solve_index += 1;
+    }
+
+    /// Takes back the last piece placed.
+    pub fn take_back(&mut self, solver_data: &SolverData) {
+        self.solve_index -= 1;
+        let cell = &solver_data.board_search_sequence[self.solve_index];
+        let b_index = cell.row as usize * solver_data.puzzle.width + cell.column as usize;
+        clear_bit(&mut self.piece_used, self.board[b_index].reid as usize);
+        self.board[b_index] = RotatedPiece::default();
+    }
+}
 
-    let mut bottom_sides: Vec<Vec<RotatedPiece>> = vec![vec![]; 529];
+/// The bottom row candidates for each left colour, those that score for the heuristic first and
+/// then in order of `jitter`, which is drawn once per candidate.
+fn bottom_sides(
+    solver_data: &SolverData,
+    mut jitter: impl FnMut() -> i32,
+) -> Vec<Vec<RotatedPiece>> {
+    let num_colours = solver_data.puzzle.num_colours;
+    let mut bottom_sides: Vec<Vec<RotatedPiece>> = vec![vec![]; num_colours * num_colours];
     for (key, value) in &solver_data.bottom_side_pieces_rotated {
         let mut pieces: Vec<(RotatedPiece, i32)> = value
             .iter()
@@ -25,55 +210,211 @@ pub fn solve_puzzle(solver_data: &SolverData) -> SolverResult {
                     100
                 } else {
                     0
-                } + rng.random_range(0..99);
+                } + jitter();
                 (x.rotated_piece, score)
             })
             .collect();
+        #[allow(clippy::unnecessary_sort_by)]
         pieces.sort_by(|a, b| b.1.cmp(&a.1));
         bottom_sides[*key as usize] = pieces.into_iter().map(|(p, _)| p).collect();
     }
+    bottom_sides
+}
 
-    let corner_list = &solver_data.corners[0];
-    let first_corner_piece = corner_list[rng.random_range(0..solver_data.corners[0].len())];
+/// Runs the search from `state` until it places every piece, runs out of placements or has
+/// visited `max_node_count` nodes. An exhaustive search carries on past full boards. Deep boards
+/// are sent to `sink` when it is given, and in an exhaustive search so is every full board. When
+/// `progress` is given the search publishes its counters to it every `PROGRESS_NODE_INTERVAL`
+/// nodes and when it finishes. When `checkpoint` is given it is called with a copy of the search
+/// state every `CHECKPOINT_NODE_INTERVAL` nodes, starting with the state it was given. When
+/// `stop` is given the search checks it every `PROGRESS_NODE_INTERVAL` nodes and, once it is
+/// set, hands a last copy of its state to `checkpoint` and returns.
+pub fn solve_puzzle(
+    solver_data: &SolverData,
+    state: SearchState,
+    max_node_count: u64,
+    sink: Option<&dyn ResultSink>,
+    progress: Option<&WorkerProgress>,
+    stop: Option<&AtomicBool>,
+    checkpoint: Option<&dyn Fn(SearchState)>,
+) -> SolverResult {
+    search::<false>(
+        solver_data,
+        state,
+        max_node_count,
+        sink,
+        progress,
+        stop,
+        checkpoint,
+    )
+}
 
-    set_bit(&mut piece_used, first_corner_piece.reid as usize);
-    cumulative_heuristic_side_count[0] = first_corner_piece.heuristic_side_count;
-    cumulative_breaks[0] = 0;
-    board[0] = first_corner_piece;
+/// Runs the same search as `solve_puzzle`, also counting the candidates it scans and the pieces
+/// it takes back. Counting slows the search a little, so `solve_puzzle` leaves it out.
+pub fn solve_puzzle_counted(
+    solver_data: &SolverData,
+    state: SearchState,
+    max_node_count: u64,
+    sink: Option<&dyn ResultSink>,
+    progress: Option<&WorkerProgress>,
+    stop: Option<&AtomicBool>,
+    checkpoint: Option<&dyn Fn(SearchState)>,
+) -> SolverResult {
+    search::<true>(
+        solver_data,
+        state,
+        max_node_count,
+        sink,
+        progress,
+        stop,
+        checkpoint,
+    )
+}
 
-    let mut solve_index: usize = 1;
-    let mut max_solve_index = solve_index;
-    let mut node_count: u64 = 0;
+fn search<const COUNT: bool>(
+    solver_data: &SolverData,
+    state: SearchState,
+    max_node_count: u64,
+    sink: Option<&dyn ResultSink>,
+    progress: Option<&WorkerProgress>,
+    stop: Option<&AtomicBool>,
+    checkpoint: Option<&dyn Fn(SearchState)>,
+) -> SolverResult {
+    let width = solver_data.puzzle.width;
+    let num_cells = solver_data.puzzle.num_cells();
+    let num_colours = solver_data.puzzle.num_colours;
+    let min_solve_index_to_save = solver_data.config.min_solve_index_to_save;
+    let max_heuristic_index = solver_data.config.max_heuristic_index;
+
+    let SearchState {
+        worker,
+        seed,
+        mut board,
+        mut piece_used,
+        mut cumulative_heuristic_side_count,
+        mut piece_index_to_try_next,
+        mut cumulative_breaks,
+        mut solve_index_counts,
+        bottom_sides,
+        mut solve_index,
+        mut max_solve_index,
+        mut node_count,
+        mut best_board,
+        root_depth,
+        exhaustive,
+    } = state;
+    if best_board.is_empty() {
+        best_board = board.clone();
+    }
+    // Counts carried over from a checkpoint were published by an earlier process, if at all.
+    let mut published_counts = solve_index_counts.clone();
+    if let Some(progress) = progress {
+        progress.record_search();
+    }
+    let mut stopped = false;
+    let mut exhausted = false;
+    let start_node_count = node_count;
+    let mut candidates_scanned: u64 = 0;
+    let mut backtracks: u64 = 0;
+    let save_board = |board: &[RotatedPiece], solve_index: usize| {
+        if let Some(sink) = sink.filter(|_| solve_index >= min_solve_index_to_save) {
+            let found = FoundBoard {
+                solver_data,
+                board,
+                worker,
+                search_seed: seed,
+            };
+            match sink.send(&found) {
+                Ok(()) => {
+                    if let Some(progress) = progress {
+                        progress.record_board_saved();
+                    }
+                }
+                Err(e) => error!("Worker {worker:02}: failed to save board: {e}"),
+            }
+        }
+    };
 
+    // Nothing needs checking between nodes unless the search is watched, stopped or checkpointed.
+    let watched = progress.is_some() || stop.is_some() || checkpoint.is_some();
     loop {
+        // The intervals are powers of two, so the check is a mask rather than a division.
+        if watched && node_count & (PROGRESS_NODE_INTERVAL - 1) == 0 {
+            if let Some(progress) = progress {
+                progress.publish(
+                    solve_index,
+                    max_solve_index,
+                    &solve_index_counts,
+                    &mut published_counts,
+                );
+            }
+
+            let stopping = stop.is_some_and(|x| x.load(Ordering::Relaxed));
+
+            if let Some(checkpoint) = checkpoint {
+                if node_count & (CHECKPOINT_NODE_INTERVAL - 1) == 0 || stopping {
+                    checkpoint(SearchState {
+                        worker,
+                        seed,
+                        board: board.clone(),
+                        piece_used: piece_used.clone(),
+                        cumulative_heuristic_side_count: cumulative_heuristic_side_count.clone(),
+                        piece_index_to_try_next: piece_index_to_try_next.clone(),
+                        cumulative_breaks: cumulative_breaks.clone(),
+                        solve_index_counts: solve_index_counts.clone(),
+                        bottom_sides: bottom_sides.clone(),
+                        solve_index,
+                        max_solve_index,
+                        node_count,
+                        best_board: best_board.clone(),
+                        root_depth,
+                        exhaustive,
+                    });
+                }
+            }
+
+            if stopping {
+                stopped = true;
+                break;
+            }
+        }
+
         node_count += 1;
 
-        // Uncomment to get this info printed.
         solve_index_counts[solve_index] += 1;
 
-        if solve_index > max_solve_index {
+        let deepest = solve_index > max_solve_index;
+        if deepest {
             max_solve_index = solve_index;
-            if solve_index >= MIN_SOLVE_INDEX_TO_SAVE {
-                save_board(&board, solve_index as u16);
-                if solve_index >= 256 {
-                    return SolverResult {
-                        solve_indexes: solve_index_counts,
-                        max_depth: max_solve_index,
-                    };
-                }
+            best_board.clone_from(&board);
+            save_board(&board, solve_index);
+        }
+
+        if solve_index >= num_cells {
+            if !exhaustive {
+                break;
             }
+            // Every full board is saved, not only the first. Then the last piece is taken back
+            // to look for others.
+            if !deepest {
+                save_board(&board, solve_index);
+            }
+            solve_index -= 1;
+            // A prefix that fills the board has nothing below it to try.
+            if solve_index < root_depth {
+                exhausted = true;
+                break;
+            }
+            continue;
         }
 
-        if node_count > MAX_NODE_COUNT {
-            return SolverResult {
-                solve_indexes: solve_index_counts,
-                max_depth: max_solve_index,
-            };
+        if node_count > max_node_count {
+            break;
         }
 
         let row = solver_data.board_search_sequence[solve_index].row as usize;
         let col = solver_data.board_search_sequence[solve_index].column as usize;
-        let b_index = row * 16 + col;
+        let b_index = row * width + col;
 
         if board[b_index].reid > 0 {
             clear_bit(&mut piece_used, board[b_index].reid as usize);
@@ -81,8 +422,8 @@ pub fn solve_puzzle(solver_data: &SolverData) -> SolverResult {
         }
 
         let candidates: &Vec<RotatedPiece> = if row == 0 {
-            let key = (board[col - 1].right as usize) * 23;
-            if col < 15 {
+            let key = (board[col - 1].right as usize) * num_colours;
+            if col < width - 1 {
                 bottom_sides[key].as_ref()
             } else {
                 solver_data.corners[key].as_ref()
@@ -91,9 +432,10 @@ pub fn solve_puzzle(solver_data: &SolverData) -> SolverResult {
             let left_side = if col == 0 {
                 0
             } else {
-                board[row * 16 + (col - 1)].right
+                board[row * width + (col - 1)].right
             };
-            let key = (left_side as usize) * 23 + (board[(row - 1) * 16 + col].top as usize);
+            let key =
+                (left_side as usize) * num_colours + (board[(row - 1) * width + col].top as usize);
             solver_data.get_pieces(solver_data.master_piece_lookup[b_index])[key].as_ref()
         };
 
@@ -101,21 +443,25 @@ pub fn solve_puzzle(solver_data: &SolverData) -> SolverResult {
 
         let breaks_this_turn =
             solver_data.break_array[solve_index] - cumulative_breaks[solve_index - 1];
-        let try_index = piece_index_to_try_next[solve_index] as usize;
+        let try_index = piece_index_to_try_next[solve_index];
         let piece_candidate_length = candidates.len();
+        // Where the scan stopped, for the count.
+        let mut scanned_to = piece_candidate_length;
 
         #[allow(clippy::needless_range_loop)]
         for i in try_index..piece_candidate_length {
             if candidates[i].breaks > breaks_this_turn {
+                scanned_to = i + 1;
                 break;
             }
 
             if is_clear(&piece_used, candidates[i].reid as usize) {
-                if solve_index <= MAX_HEURISTIC_INDEX
+                if solve_index <= max_heuristic_index
                     && ((cumulative_heuristic_side_count[solve_index - 1]
                         + candidates[i].heuristic_side_count)
                         < solver_data.heuristic_array[solve_index] as u8)
                 {
+                    scanned_to = i + 1;
                     break;
                 }
 
@@ -126,15 +472,50 @@ pub fn solve_puzzle(solver_data: &SolverData) -> SolverResult {
                 cumulative_breaks[solve_index] = cumulative_breaks[solve_index - 1] + piece.breaks;
                 cumulative_heuristic_side_count[solve_index] =
                     cumulative_heuristic_side_count[solve_index - 1] + piece.heuristic_side_count;
-                piece_index_to_try_next[solve_index] = (i + 1) as u8;
+                piece_index_to_try_next[solve_index] = i + 1;
                 solve_index += 1;
+                scanned_to = i + 1;
                 break;
             }
         }
+        if COUNT {
+            candidates_scanned += scanned_to.saturating_sub(try_index) as u64;
+        }
 
         if !found_piece {
             piece_index_to_try_next[solve_index] = 0;
             solve_index -= 1;
+            if COUNT {
+                backtracks += 1;
+            }
+
+            // Every placement below the fixed ones has been tried. Small boards can get here
+            // from their first corner.
+            if solve_index < root_depth {
+                exhausted = true;
+                break;
+            }
         }
     }
+
+    if let Some(progress) = progress {
+        progress.publish(
+            solve_index,
+            max_solve_index,
+            &solve_index_counts,
+            &mut published_counts,
+        );
+    }
+
+    SolverResult {
+        solve_indexes: solve_index_counts,
+        max_depth: max_solve_index,
+        best_score: score_board(&solver_data.puzzle, &best_board),
+        best_board,
+        stopped,
+        exhausted,
+        nodes: node_count - start_node_count,
+        candidates_scanned,
+        backtracks,
+    }
 }
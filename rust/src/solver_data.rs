@@ -1,14 +1,13 @@
 use crate::board_order::get_board_order;
-use crate::config::BREAK_INDEXES_ALLOWED;
-use crate::config::{HEURISTIC_SIDES, MAX_HEURISTIC_INDEX};
-use crate::pieces;
+use crate::config::SolverConfig;
+use crate::puzzle::Puzzle;
 use crate::structs::{Piece, RotatedPiece, RotatedPieceWithLeftBottom, SearchIndex};
-use rand::Rng;
-use std::collections::HashMap;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
-const SIDE_EDGES: &[u8] = &[1, 5, 9, 13, 17];
-
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum PieceCategory {
     None,
     Corners,
@@ -23,7 +22,11 @@ pub enum PieceCategory {
     Start,
 }
 
+#[derive(Serialize, Deserialize)]
 pub struct SolverData {
+    pub puzzle: Puzzle,
+    pub config: SolverConfig,
+    pub seed: u64,
     pub no_pieces: Vec<Vec<RotatedPiece>>,
     pub corners: Vec<Vec<RotatedPiece>>,
     left_sides: Vec<Vec<RotatedPiece>>,
@@ -35,10 +38,10 @@ pub struct SolverData {
     south_start: Vec<Vec<RotatedPiece>>,
     west_start: Vec<Vec<RotatedPiece>>,
     start: Vec<Vec<RotatedPiece>>,
-    pub bottom_side_pieces_rotated: HashMap<u16, Vec<RotatedPieceWithLeftBottom>>,
-    pub master_piece_lookup: [PieceCategory; 256],
-    pub board_search_sequence: [SearchIndex; 256],
-    pub break_array: [u8; 256],
+    pub bottom_side_pieces_rotated: BTreeMap<u16, Vec<RotatedPieceWithLeftBottom>>,
+    pub master_piece_lookup: Vec<PieceCategory>,
+    pub board_search_sequence: Vec<SearchIndex>,
+    pub break_array: Vec<u8>,
     pub heuristic_array: Vec<i32>,
 }
 
@@ -60,15 +63,42 @@ impl SolverData {
     }
 }
 
-fn calculate_two_sides(side1: u16, side2: u16) -> u16 {
-    (side1 * 23) + side2
+fn calculate_two_sides(side1: u16, side2: u16, num_colours: u16) -> u16 {
+    (side1 * num_colours) + side2
+}
+
+/// Returns the colours that border pieces have next to their grey edges. Breaks on these sides
+/// are never allowed.
+fn get_side_edges(board_pieces: &[Piece]) -> Vec<u8> {
+    let mut side_edges: Vec<u8> = board_pieces
+        .iter()
+        .flat_map(|x| match x.piece_type() {
+            2 => vec![x.top, x.right],
+            1 => vec![x.left, x.right],
+            _ => vec![],
+        })
+        .collect();
+    side_edges.sort_unstable();
+    side_edges.dedup();
+    side_edges
+}
+
+/// The puzzle-wide settings that decide which rotations of a piece are allowed and how they score.
+struct RotationRules<'a> {
+    num_colours: u16,
+    side_edges: &'a [u8],
+    heuristic_sides: &'a [u8],
 }
 
-fn get_rotated_pieces(piece: &Piece, allow_breaks: bool) -> Vec<RotatedPieceWithLeftBottom> {
+fn get_rotated_pieces(
+    piece: &Piece,
+    allow_breaks: bool,
+    rules: &RotationRules,
+) -> Vec<RotatedPieceWithLeftBottom> {
     let mut score_base: i32 = 0;
     let mut heuristic_side_count: u8 = 0;
 
-    for &side in HEURISTIC_SIDES {
+    for &side in rules.heuristic_sides {
         if piece.left == side {
             score_base += 100;
             heuristic_side_count += 1;
@@ -89,8 +119,8 @@ fn get_rotated_pieces(piece: &Piece, allow_breaks: bool) -> Vec<RotatedPieceWith
 
     let mut rotated_pieces = Vec::new();
 
-    for left in 0..=22u16 {
-        for bottom in 0..=22u16 {
+    for left in 0..rules.num_colours {
+        for bottom in 0..rules.num_colours {
             check_and_add_rotation(
                 &mut rotated_pieces,
                 piece,
@@ -104,6 +134,7 @@ fn get_rotated_pieces(piece: &Piece, allow_breaks: bool) -> Vec<RotatedPieceWith
                 score_base,
                 heuristic_side_count,
                 allow_breaks,
+                rules,
             );
 
             check_and_add_rotation(
@@ -119,6 +150,7 @@ fn get_rotated_pieces(piece: &Piece, allow_breaks: bool) -> Vec<RotatedPieceWith
                 score_base,
                 heuristic_side_count,
                 allow_breaks,
+                rules,
             );
 
             check_and_add_rotation(
@@ -134,6 +166,7 @@ fn get_rotated_pieces(piece: &Piece, allow_breaks: bool) -> Vec<RotatedPieceWith
                 score_base,
                 heuristic_side_count,
                 allow_breaks,
+                rules,
             );
 
             check_and_add_rotation(
@@ -149,6 +182,7 @@ fn get_rotated_pieces(piece: &Piece, allow_breaks: bool) -> Vec<RotatedPieceWith
                 score_base,
                 heuristic_side_count,
                 allow_breaks,
+                rules,
             );
         }
     }
@@ -169,26 +203,27 @@ fn check_and_add_rotation(
     score_base: i32,
     h_count: u8,
     allow_breaks: bool,
+    rules: &RotationRules,
 ) {
     let mut breaks: u8 = 0;
     let mut side_breaks: u8 = 0;
 
     if p_side_l != target_l as u8 {
         breaks += 1;
-        if SIDE_EDGES.contains(&p_side_l) {
+        if rules.side_edges.contains(&p_side_l) {
             side_breaks += 1;
         }
     }
     if p_side_b != target_b as u8 {
         breaks += 1;
-        if SIDE_EDGES.contains(&p_side_b) {
+        if rules.side_edges.contains(&p_side_b) {
             side_breaks += 1;
         }
     }
 
     if ((breaks == 0) || (breaks == 1 && allow_breaks)) && side_breaks == 0 {
         list.push(RotatedPieceWithLeftBottom {
-            left_bottom: calculate_two_sides(target_l, target_b),
+            left_bottom: calculate_two_sides(target_l, target_b, rules.num_colours),
             score: score_base - (100_000 * breaks as i32),
             rotated_piece: RotatedPiece {
                 reid: piece.reid,
@@ -202,16 +237,20 @@ fn check_and_add_rotation(
     }
 }
 
-fn first_break_index() -> usize {
-    *BREAK_INDEXES_ALLOWED.iter().min().unwrap_or(&256)
+fn first_break_index(config: &SolverConfig, num_cells: usize) -> usize {
+    *config
+        .break_indexes_allowed
+        .iter()
+        .min()
+        .unwrap_or(&num_cells)
 }
 
-fn get_break_array() -> [u8; 256] {
-    let mut cumulative_breaks = [0u8; 256];
+fn get_break_array(config: &SolverConfig, num_cells: usize) -> Vec<u8> {
+    let mut cumulative_breaks = vec![0u8; num_cells];
     let mut count = 0;
     #[allow(clippy::needless_range_loop)]
-    for i in 0..256 {
-        if BREAK_INDEXES_ALLOWED.contains(&i) {
+    for i in 0..num_cells {
+        if config.break_indexes_allowed.contains(&i) {
             count += 1;
         }
         cumulative_breaks[i] = count;
@@ -219,31 +258,45 @@ fn get_break_array() -> [u8; 256] {
     cumulative_breaks
 }
 
-fn get_heuristic_array() -> Vec<i32> {
-    let mut heuristic_array = vec![0i32; 256];
+fn get_heuristic_array(config: &SolverConfig, num_cells: usize) -> Vec<i32> {
+    let mut heuristic_array = vec![0i32; num_cells];
     #[allow(clippy::needless_range_loop)]
-    for i in 0..256 {
-        heuristic_array[i] = if i <= 16 {
-            0
-        } else if i <= 26 {
-            ((i as f32 - 16.0) * 2.8) as i32
-        } else if i <= 56 {
-            (((i as f32 - 26.0) * 1.43333) + 28.0) as i32
-        } else if i <= 76 {
-            (((i as f32 - 56.0) * 0.9) + 71.0) as i32
-        } else if i <= 102 {
-            (((i as f32 - 76.0) * 0.6538) + 89.0) as i32
-        } else if i <= MAX_HEURISTIC_INDEX {
-            (((i as f32 - 102.0) / 4.4615) + 106.0) as i32
-        } else {
-            0
-        };
+    for i in 0..=config.max_heuristic_index.min(num_cells - 1) {
+        if let Some(segment) = config.heuristic_curve.iter().find(|x| i <= x.until) {
+            heuristic_array[i] =
+                ((i as f32 - segment.from as f32) * segment.slope + segment.offset) as i32;
+        }
     }
     heuristic_array
 }
 
-pub fn prepare_pieces_and_heuristics() -> SolverData {
-    let board_pieces = pieces::PIECES;
+/// Builds the candidate tables for a puzzle. `seed` drives the random jitter in the candidate
+/// order, so the same seed always gives the same tables.
+pub fn prepare_pieces_and_heuristics(
+    puzzle: &Puzzle,
+    config: &SolverConfig,
+    seed: u64,
+) -> SolverData {
+    let board_pieces = &puzzle.pieces;
+    let width = puzzle.width;
+    let height = puzzle.height;
+    let num_cells = puzzle.num_cells();
+    let num_colours = puzzle.num_colours as u16;
+    let side_edges = get_side_edges(board_pieces);
+    let rules = RotationRules {
+        num_colours,
+        side_edges: &side_edges,
+        heuristic_sides: &config.heuristic_sides,
+    };
+
+    let start_reid = puzzle.start_piece.map_or(0, |x| x.reid);
+    let start_rotations = puzzle.start_piece.map(|x| x.rotations);
+    let start_sides = puzzle.start_piece.and_then(|clue| {
+        board_pieces
+            .iter()
+            .find(|x| x.reid == clue.reid)
+            .map(|x| x.rotated(clue.rotations))
+    });
 
     let corner_pieces: Vec<_> = board_pieces
         .iter()
@@ -259,31 +312,31 @@ pub fn prepare_pieces_and_heuristics() -> SolverData {
 
     let middle_pieces: Vec<_> = board_pieces
         .iter()
-        .filter(|x| x.piece_type() == 0 && x.reid != 139)
+        .filter(|x| x.piece_type() == 0 && x.reid != start_reid)
         .cloned()
         .collect();
 
     let start_piece: Vec<_> = board_pieces
         .iter()
-        .filter(|x| x.reid == 139)
+        .filter(|x| x.reid == start_reid)
         .cloned()
         .collect();
 
     let corner_pieces_rotated = group_by_left_bottom(
         corner_pieces
             .iter()
-            .flat_map(|x| get_rotated_pieces(x, false))
+            .flat_map(|x| get_rotated_pieces(x, false, &rules))
             .collect(),
     );
 
     let sides_without_breaks: Vec<_> = side_pieces
         .iter()
-        .flat_map(|x| get_rotated_pieces(x, false))
+        .flat_map(|x| get_rotated_pieces(x, false, &rules))
         .collect();
 
     let sides_with_breaks: Vec<_> = side_pieces
         .iter()
-        .flat_map(|x| get_rotated_pieces(x, true))
+        .flat_map(|x| get_rotated_pieces(x, true, &rules))
         .collect();
 
     let bottom_side_pieces_rotated = group_by_left_bottom(
@@ -329,113 +382,116 @@ pub fn prepare_pieces_and_heuristics() -> SolverData {
     let middle_pieces_rotated_with_breaks = group_by_left_bottom(
         middle_pieces
             .iter()
-            .flat_map(|x| get_rotated_pieces(x, true))
+            .flat_map(|x| get_rotated_pieces(x, true, &rules))
             .collect(),
     );
 
     let middle_pieces_rotated_without_breaks = group_by_left_bottom(
         middle_pieces
             .iter()
-            .flat_map(|x| get_rotated_pieces(x, false))
+            .flat_map(|x| get_rotated_pieces(x, false, &rules))
             .collect(),
     );
 
     let south_start_piece_rotated = group_by_left_bottom(
         middle_pieces
             .iter()
-            .flat_map(|x| get_rotated_pieces(x, false))
-            .filter(|x| x.rotated_piece.top == 6)
+            .flat_map(|x| get_rotated_pieces(x, false, &rules))
+            .filter(|x| start_sides.is_some_and(|s| x.rotated_piece.top == s.bottom))
             .collect(),
     );
 
     let west_start_piece_rotated = group_by_left_bottom(
         middle_pieces
             .iter()
-            .flat_map(|x| get_rotated_pieces(x, false))
-            .filter(|x| x.rotated_piece.right == 11)
+            .flat_map(|x| get_rotated_pieces(x, false, &rules))
+            .filter(|x| start_sides.is_some_and(|s| x.rotated_piece.right == s.left))
             .collect(),
     );
 
     let start_piece_rotated = group_by_left_bottom(
         start_piece
             .iter()
-            .flat_map(|x| get_rotated_pieces(x, false))
-            .filter(|x| x.rotated_piece.rotations == 2)
+            .flat_map(|x| get_rotated_pieces(x, false, &rules))
+            .filter(|x| Some(x.rotated_piece.rotations) == start_rotations)
             .collect(),
     );
 
-    let mut rng = rand::rng();
+    let mut rng = StdRng::seed_from_u64(seed);
 
     let no_pieces: Vec<Vec<RotatedPiece>> = vec![];
-    let corners = create_sorted_array(&corner_pieces_rotated, &mut rng);
-    let left_sides = create_sorted_array(&left_side_pieces_rotated, &mut rng);
-    let top_sides = create_sorted_array(&top_side_pieces_rotated, &mut rng);
-    let right_sides_with_breaks =
-        create_sorted_array(&right_side_pieces_with_breaks_rotated, &mut rng);
-    let right_sides_without_breaks =
-        create_sorted_array(&right_side_pieces_without_breaks_rotated, &mut rng);
-    let middles_with_break = create_sorted_array(&middle_pieces_rotated_with_breaks, &mut rng);
-    let middles_no_break = create_sorted_array(&middle_pieces_rotated_without_breaks, &mut rng);
-    let south_start = create_sorted_array(&south_start_piece_rotated, &mut rng);
-    let west_start = create_sorted_array(&west_start_piece_rotated, &mut rng);
-    let start = create_sorted_array(&start_piece_rotated, &mut rng);
-
-    let board_search_sequence = get_board_order();
-    let break_array = get_break_array();
-
-    let mut master_piece_lookup: [PieceCategory; 256] = [PieceCategory::None; 256];
+    let corners = create_sorted_array(&corner_pieces_rotated, num_colours, &mut rng);
+    let left_sides = create_sorted_array(&left_side_pieces_rotated, num_colours, &mut rng);
+    let top_sides = create_sorted_array(&top_side_pieces_rotated, num_colours, &mut rng);
+    let right_sides_with_breaks = create_sorted_array(
+        &right_side_pieces_with_breaks_rotated,
+        num_colours,
+        &mut rng,
+    );
+    let right_sides_without_breaks = create_sorted_array(
+        &right_side_pieces_without_breaks_rotated,
+        num_colours,
+        &mut rng,
+    );
+    let middles_with_break =
+        create_sorted_array(&middle_pieces_rotated_with_breaks, num_colours, &mut rng);
+    let middles_no_break =
+        create_sorted_array(&middle_pieces_rotated_without_breaks, num_colours, &mut rng);
+    let south_start = create_sorted_array(&south_start_piece_rotated, num_colours, &mut rng);
+    let west_start = create_sorted_array(&west_start_piece_rotated, num_colours, &mut rng);
+    let start = create_sorted_array(&start_piece_rotated, num_colours, &mut rng);
+
+    let board_search_sequence = get_board_order(&config.board_order);
+    let break_array = get_break_array(config, num_cells);
+    let first_break_index = first_break_index(config, num_cells);
+    let (start_row, start_col) = puzzle.start_piece.map_or((usize::MAX, usize::MAX), |x| {
+        (x.row as usize, x.column as usize)
+    });
+
+    let mut master_piece_lookup = vec![PieceCategory::None; num_cells];
 
     #[allow(clippy::needless_range_loop)]
-    for i in 0..256 {
+    for i in 0..num_cells {
         let row = board_search_sequence[i].row as usize;
         let col = board_search_sequence[i].column as usize;
 
-        let lookup = if row == 15 {
-            if col == 15 || col == 0 {
+        let lookup = if row == height - 1 {
+            if col == width - 1 || col == 0 {
                 PieceCategory::Corners
             } else {
                 PieceCategory::TopSides
             }
         } else if row == 0 {
             PieceCategory::None
-        } else if col == 15 {
-            if i < first_break_index() {
+        } else if col == width - 1 {
+            if i < first_break_index {
                 PieceCategory::RightSidesWithoutBreaks
             } else {
                 PieceCategory::RightSidesWithBreaks
             }
         } else if col == 0 {
             PieceCategory::LeftSides
-        } else if row == 7 {
-            if col == 7 {
-                PieceCategory::Start
-            } else if col == 6 {
-                PieceCategory::WestStart
-            } else if i < first_break_index() {
-                PieceCategory::MiddlesNoBreak
-            } else {
-                PieceCategory::MiddlesWithBreak
-            }
-        } else if row == 6 {
-            if col == 7 {
-                PieceCategory::SouthStart
-            } else if i < first_break_index() {
-                PieceCategory::MiddlesNoBreak
-            } else {
-                PieceCategory::MiddlesWithBreak
-            }
-        } else if i < first_break_index() {
+        } else if row == start_row && col == start_col {
+            PieceCategory::Start
+        } else if row == start_row && col + 1 == start_col {
+            PieceCategory::WestStart
+        } else if row + 1 == start_row && col == start_col {
+            PieceCategory::SouthStart
+        } else if i < first_break_index {
             PieceCategory::MiddlesNoBreak
         } else {
             PieceCategory::MiddlesWithBreak
         };
 
-        master_piece_lookup[row * 16 + col] = lookup;
+        master_piece_lookup[row * width + col] = lookup;
     }
 
-    let heuristic_array = get_heuristic_array();
+    let heuristic_array = get_heuristic_array(config, num_cells);
 
     SolverData {
+        puzzle: puzzle.clone(),
+        config: config.clone(),
+        seed,
         no_pieces,
         corners,
         left_sides,
@@ -455,10 +511,11 @@ pub fn prepare_pieces_and_heuristics() -> SolverData {
     }
 }
 
+// Grouped into a BTreeMap so that the seeded shuffles always visit the groups in the same order.
 fn group_by_left_bottom(
     pieces: Vec<RotatedPieceWithLeftBottom>,
-) -> HashMap<u16, Vec<RotatedPieceWithLeftBottom>> {
-    let mut map: HashMap<u16, Vec<RotatedPieceWithLeftBottom>> = HashMap::new();
+) -> BTreeMap<u16, Vec<RotatedPieceWithLeftBottom>> {
+    let mut map: BTreeMap<u16, Vec<RotatedPieceWithLeftBottom>> = BTreeMap::new();
     for piece in pieces {
         map.entry(piece.left_bottom).or_default().push(piece);
     }
@@ -466,10 +523,11 @@ fn group_by_left_bottom(
 }
 
 fn create_sorted_array(
-    map: &HashMap<u16, Vec<RotatedPieceWithLeftBottom>>,
+    map: &BTreeMap<u16, Vec<RotatedPieceWithLeftBottom>>,
+    num_colours: u16,
     rng: &mut impl Rng,
 ) -> Vec<Vec<RotatedPiece>> {
-    let mut result = vec![Vec::new(); 529];
+    let mut result = vec![Vec::new(); num_colours as usize * num_colours as usize];
 
     for (key, value) in map {
         let mut pieces: Vec<(RotatedPiece, i32)> = value
@@ -477,9 +535,194 @@ fn create_sorted_array(
             .map(|x| (x.rotated_piece, x.score + rng.random_range(0..99)))
             .collect();
 
+        #[allow(clippy::unnecessary_sort_by)]
         pieces.sort_by(|a, b| b.1.cmp(&a.1));
 
         result[*key as usize] = pieces.into_iter().map(|(p, _)| p).collect();
     }
     result
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    const PIECE: Piece = Piece {
+        reid: 7,
+        top: 1,
+        right: 2,
+        bottom: 3,
+        left: 4,
+    };
+
+    fn rules<'a>(side_edges: &'a [u8], heuristic_sides: &'a [u8]) -> RotationRules<'a> {
+        RotationRules {
+            num_colours: 5,
+            side_edges,
+            heuristic_sides,
+        }
+    }
+
+    #[test]
+    fn rotations_match_the_turned_piece() {
+        let rotated = get_rotated_pieces(&PIECE, false, &rules(&[], &[]));
+        let mut rotations: Vec<_> = rotated.iter().map(|x| x.rotated_piece.rotations).collect();
+        rotations.sort_unstable();
+        assert_eq!(rotations, [0, 1, 2, 3]);
+        for x in &rotated {
+            let turned = PIECE.rotated(x.rotated_piece.rotations);
+            assert_eq!(x.rotated_piece.reid, PIECE.reid);
+            assert_eq!(x.rotated_piece.top, turned.top);
+            assert_eq!(x.rotated_piece.right, turned.right);
+            assert_eq!(x.rotated_piece.breaks, 0);
+            assert_eq!(
+                x.left_bottom,
+                calculate_two_sides(turned.left as u16, turned.bottom as u16, 5)
+            );
+        }
+    }
+
+    #[test]
+    fn breaks_allow_one_mismatched_side() {
+        let rotated = get_rotated_pieces(&PIECE, true, &rules(&[], &[]));
+        // Each rotation fits its own sides, and any of the 4 other colours on one of them.
+        assert_eq!(rotated.len(), 4 * (1 + 2 * 4));
+        for x in &rotated {
+            let expected = if x.rotated_piece.breaks == 0 {
+                0
+            } else {
+                -100_000
+            };
+            assert!(x.rotated_piece.breaks <= 1);
+            assert_eq!(x.score, expected);
+        }
+    }
+
+    #[test]
+    fn two_breaks_are_never_allowed() {
+        let mut list = vec![];
+        check_and_add_rotation(
+            &mut list,
+            &PIECE,
+            0,
+            0,
+            0,
+            PIECE.left,
+            PIECE.bottom,
+            PIECE.top,
+            PIECE.right,
+            0,
+            0,
+            true,
+            &rules(&[], &[]),
+        );
+        assert!(list.is_empty());
+    }
+
+    #[test]
+    fn breaks_on_side_edge_colours_are_not_allowed() {
+        let rotated = get_rotated_pieces(&PIECE, true, &rules(&[4], &[]));
+        for x in rotated.iter().filter(|x| x.rotated_piece.breaks > 0) {
+            let turned = PIECE.rotated(x.rotated_piece.rotations);
+            let broken_side = if x.left_bottom / 5 != turned.left as u16 {
+                turned.left
+            } else {
+                turned.bottom
+            };
+            assert_ne!(broken_side, 4);
+        }
+        // Two rotations have colour 4 on their left or bottom, and each loses its 4 breaks there.
+        assert_eq!(rotated.len(), 4 * (1 + 2 * 4) - 2 * 4);
+    }
+
+    #[test]
+    fn heuristic_sides_are_counted_on_every_side() {
+        let piece = Piece { left: 1, ..PIECE };
+        let rotated = get_rotated_pieces(&piece, false, &rules(&[], &[1, 3]));
+        for x in &rotated {
+            assert_eq!(x.rotated_piece.heuristic_side_count, 3);
+            assert_eq!(x.score, 300);
+        }
+    }
+
+    #[test]
+    fn groups_keep_their_order() {
+        let rotated = get_rotated_pieces(&PIECE, true, &rules(&[], &[]));
+        let groups = group_by_left_bottom(rotated.clone());
+        assert!(groups.keys().is_sorted());
+        assert_eq!(groups.values().map(Vec::len).sum::<usize>(), rotated.len());
+        for (key, group) in &groups {
+            let expected: Vec<_> = rotated.iter().filter(|x| x.left_bottom == *key).collect();
+            assert_eq!(group.iter().collect::<Vec<_>>(), expected);
+        }
+    }
+
+    #[test]
+    fn sorted_candidates_put_breaks_last_and_heuristic_sides_first() {
+        let pieces: Vec<_> = (1..=6)
+            .map(|reid| Piece {
+                reid,
+                top: reid as u8 % 3,
+                ..PIECE
+            })
+            .collect();
+        let groups = group_by_left_bottom(
+            pieces
+                .iter()
+                .flat_map(|x| get_rotated_pieces(x, true, &rules(&[], &[2])))
+                .collect(),
+        );
+        let mut rng = StdRng::seed_from_u64(1);
+        for candidates in create_sorted_array(&groups, 5, &mut rng) {
+            // The search stops at the first candidate with too many breaks or too few heuristic
+            // sides, which is only right if they come in this order.
+            let order: Vec<_> = candidates
+                .iter()
+                .map(|x| (x.breaks, std::cmp::Reverse(x.heuristic_side_count)))
+                .collect();
+            assert!(order.is_sorted());
+        }
+    }
+
+    #[test]
+    fn break_array_counts_the_breaks_allowed_so_far() {
+        let config = SolverConfig {
+            break_indexes_allowed: vec![2, 5, 6],
+            ..SolverConfig::for_board(3, 3)
+        };
+        assert_eq!(get_break_array(&config, 9), [0, 0, 1, 1, 1, 2, 3, 3, 3]);
+        assert_eq!(first_break_index(&config, 9), 2);
+    }
+
+    proptest! {
+        #[test]
+        fn every_rotation_fits_its_left_and_bottom(
+            sides in prop::array::uniform4(0u8..6),
+            allow_breaks: bool,
+        ) {
+            let piece = Piece {
+                reid: 1,
+                top: sides[0],
+                right: sides[1],
+                bottom: sides[2],
+                left: sides[3],
+            };
+            let rules = RotationRules {
+                num_colours: 6,
+                side_edges: &[],
+                heuristic_sides: &[],
+            };
+            for x in get_rotated_pieces(&piece, allow_breaks, &rules) {
+                let turned = piece.rotated(x.rotated_piece.rotations);
+                let left = (x.left_bottom / 6) as u8;
+                let bottom = (x.left_bottom % 6) as u8;
+                let mismatches = (turned.left != left) as u8 + (turned.bottom != bottom) as u8;
+                prop_assert_eq!(x.rotated_piece.top, turned.top);
+                prop_assert_eq!(x.rotated_piece.right, turned.right);
+                prop_assert_eq!(x.rotated_piece.breaks, mismatches);
+                prop_assert!(mismatches == 0 || (allow_breaks && mismatches == 1));
+            }
+        }
+    }
+}
@@ -1,4 +1,6 @@
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Piece {
     pub reid: u16, // Real ID (1-256).
     pub top: u8,
@@ -10,15 +12,31 @@ pub struct Piece {
 impl Piece {
     /// Returns the type of piece: 2 for corners, 1 for sides, and 0 for middles
     pub fn piece_type(&self) -> u8 {
-        match self.reid {
-            1..=4 => 2,
-            5..=60 => 1,
-            _ => 0,
+        [self.top, self.right, self.bottom, self.left]
+            .iter()
+            .filter(|&&side| side == 0)
+            .count() as u8
+    }
+
+    /// Returns the piece turned clockwise the given number of quarter turns.
+    pub fn rotated(&self, rotations: u8) -> Piece {
+        let (top, right, bottom, left) = match rotations % 4 {
+            0 => (self.top, self.right, self.bottom, self.left),
+            1 => (self.left, self.top, self.right, self.bottom),
+            2 => (self.bottom, self.left, self.top, self.right),
+            _ => (self.right, self.bottom, self.left, self.top),
+        };
+        Piece {
+            reid: self.reid,
+            top,
+            right,
+            bottom,
+            left,
         }
     }
 }
 
-#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct RotatedPiece {
     pub reid: u16,
     pub rotations: u8,
@@ -28,20 +46,80 @@ pub struct RotatedPiece {
     pub heuristic_side_count: u8,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct RotatedPieceWithLeftBottom {
     pub left_bottom: u16,
     pub score: i32,
     pub rotated_piece: RotatedPiece,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct SearchIndex {
     pub row: u8,
     pub column: u8,
 }
 
+/// Counts for a board that may be only partly filled.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BoardScore {
+    /// Pieces on the board.
+    pub placed: usize,
+    /// Edges between neighbouring pieces whose colours differ.
+    pub breaks: usize,
+    /// Edges between neighbouring pieces whose colours match.
+    pub matched_edges: usize,
+}
+
+#[derive(Serialize, Deserialize)]
 pub struct SolverResult {
-    pub solve_indexes: [u64; 257],
+    /// How many nodes were visited at each position in the search order.
+    pub solve_indexes: Vec<u64>,
     pub max_depth: usize,
+    /// The board as it stood when `max_depth` was first reached, indexed by `row * width + column`
+    /// from the bottom left. Empty cells have a `reid` of 0.
+    pub best_board: Vec<RotatedPiece>,
+    pub best_score: BoardScore,
+    /// Whether the search was asked to stop before it finished.
+    pub stopped: bool,
+    /// Whether the search tried every placement below the ones it started from, rather than
+    /// ending at a full board or its node budget.
+    pub exhausted: bool,
+    /// How many nodes this call visited, leaving out any counted before a checkpoint.
+    #[serde(default)]
+    pub nodes: u64,
+    /// How many candidates this call looked at, whether or not they fitted. Only counted by
+    /// `solve_puzzle_counted`.
+    #[serde(default)]
+    pub candidates_scanned: u64,
+    /// How many times this call took a piece back because nothing more fitted after it. Only
+    /// counted by `solve_puzzle_counted`.
+    #[serde(default)]
+    pub backtracks: u64,
+}
+
+/// A placed piece in a `BoardRecord`, with rows counted from the bottom.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CellRecord {
+    pub row: usize,
+    pub column: usize,
+    pub reid: u16,
+    pub rotations: u8,
+}
+
+/// A saved board in the JSON output formats.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BoardRecord {
+    pub depth: usize,
+    pub width: usize,
+    pub height: usize,
+    pub cells: Vec<CellRecord>,
+    pub breaks: usize,
+    pub matched_edges: usize,
+    /// When the board was found, in RFC 3339 format.
+    pub timestamp: String,
+    pub solver_data_seed: u64,
+    pub search_seed: u64,
+    pub profile: Option<String>,
+    pub worker: usize,
+    pub url: String,
 }
@@ -1,56 +1,150 @@
-use crate::pieces;
-use crate::structs::RotatedPiece;
-use std::fs;
+use crate::puzzle::Puzzle;
+use crate::solver_data::SolverData;
+use crate::structs::{BoardRecord, BoardScore, CellRecord, Piece, RotatedPiece};
+use chrono::Utc;
 
-pub fn save_board(board: &[RotatedPiece; 256], max_solve_index: u16) {
-    let board_pieces = pieces::PIECES;
+/// Derives a seed from a master seed and a path such as `[loop, core, repeat]`, so every part of
+/// a run gets its own well-mixed random stream.
+pub fn derive_seed(master_seed: u64, path: &[u64]) -> u64 {
+    path.iter().fold(splitmix64(master_seed), |seed, &part| {
+        splitmix64(seed ^ splitmix64(part))
+    })
+}
+
+fn splitmix64(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Formats a board as rows of `reid/rotations`, top row first.
+pub fn format_board(puzzle: &Puzzle, board: &[RotatedPiece]) -> String {
+    let width = puzzle.width;
     let mut entire_board = String::new();
-    let mut url_path = String::new();
 
-    for i in (0..16).rev() {
+    for i in (0..puzzle.height).rev() {
         let mut row_str = String::new();
-        for j in 0..16 {
-            let p_rotated = board[i * 16 + j];
+        for j in 0..width {
+            let p_rotated = board[i * width + j];
             if p_rotated.reid > 0 {
                 row_str.push_str(&format!("{:>3}/{} ", p_rotated.reid, p_rotated.rotations));
-
-                // Find original piece to get sides for URL.
-                if let Some(p) = board_pieces.iter().find(|k| k.reid == p_rotated.reid) {
-                    let (t, r, b, l) = match p_rotated.rotations {
-                        0 => (p.top, p.right, p.bottom, p.left),
-                        1 => (p.left, p.top, p.right, p.bottom),
-                        2 => (p.bottom, p.left, p.top, p.right),
-                        _ => (p.right, p.bottom, p.left, p.top),
-                    };
-                    for side in [t, r, b, l] {
-                        url_path.push((side + b'a') as char);
-                    }
-                }
             } else {
                 row_str.push_str("---/- ");
-                url_path.push_str("aaaa");
             }
         }
         entire_board.push_str(&row_str);
         entire_board.push('\n');
     }
+    entire_board
+}
 
-    let final_output = format!(
-        "{entire_board}\nhttps://e2.bucas.name/#puzzle=Joshua_Blackwood&board_w=16&board_h=16&board_edges={url_path}&motifs_order=jblackwood",
-    );
+/// Returns a link that shows the board on e2.bucas.name.
+pub fn board_url(puzzle: &Puzzle, board: &[RotatedPiece]) -> String {
+    let width = puzzle.width;
+    let mut url_path = String::new();
 
-    let hash = format!("{:x}", md5::compute(&final_output));
-    let filename = format!(
-        "{}_{}_{}.txt",
-        max_solve_index,
-        hash,
-        rand::random::<u32>() % 1_000_000
+    for i in (0..puzzle.height).rev() {
+        for j in 0..width {
+            match puzzle.placed_piece(&board[i * width + j]) {
+                Some(p) => {
+                    for side in [p.top, p.right, p.bottom, p.left] {
+                        url_path.push((side + b'a') as char);
+                    }
+                }
+                None => url_path.push_str("aaaa"),
+            }
+        }
+    }
+
+    format!(
+        "https://e2.bucas.name/#puzzle=Joshua_Blackwood&board_w={width}&board_h={}&board_edges={url_path}&motifs_order=jblackwood",
+        puzzle.height
+    )
+}
+
+/// Counts the placed pieces on a board, and the matched and unmatched edges between them.
+pub fn score_board(puzzle: &Puzzle, board: &[RotatedPiece]) -> BoardScore {
+    let width = puzzle.width;
+    let placed: Vec<Option<Piece>> = board.iter().map(|x| puzzle.placed_piece(x)).collect();
+    let mut score = BoardScore {
+        placed: placed.iter().flatten().count(),
+        ..BoardScore::default()
+    };
+
+    for row in 0..puzzle.height {
+        for col in 0..width {
+            let Some(piece) = placed[row * width + col] else {
+                continue;
+            };
+            let mut neighbours = vec![];
+            if col + 1 < width {
+                neighbours.push((piece.right, placed[row * width + col + 1].map(|x| x.left)));
+            }
+            if row + 1 < puzzle.height {
+                neighbours.push((piece.top, placed[(row + 1) * width + col].map(|x| x.bottom)));
+            }
+            for (side, other_side) in neighbours {
+                match other_side {
+                    Some(other_side) if other_side == side => score.matched_edges += 1,
+                    Some(_) => score.breaks += 1,
+                    None => {}
+                }
+            }
+        }
+    }
+    score
+}
+
+/// Formats a board as saved in the text output format: the grid, the e2.bucas.name link, the
+/// seeds that produced it and the profile that records the rest of the run's settings.
+pub fn board_text(solver_data: &SolverData, board: &[RotatedPiece], search_seed: u64) -> String {
+    let puzzle = &solver_data.puzzle;
+    let mut text = format!(
+        "{}\n{}\n\nSolver data seed: {}\nSearch seed: {search_seed}\n",
+        format_board(puzzle, board),
+        board_url(puzzle, board),
+        solver_data.seed
     );
+    if let Some(profile) = &solver_data.config.profile {
+        text += &format!("Profile: {profile}\n");
+    }
+    text
+}
+
+/// Describes a board for the JSON output formats.
+pub fn board_record(
+    solver_data: &SolverData,
+    board: &[RotatedPiece],
+    worker: usize,
+    search_seed: u64,
+) -> BoardRecord {
+    let puzzle = &solver_data.puzzle;
+    let score = score_board(puzzle, board);
+    let cells = board
+        .iter()
+        .enumerate()
+        .filter(|(_, x)| x.reid > 0)
+        .map(|(i, x)| CellRecord {
+            row: i / puzzle.width,
+            column: i % puzzle.width,
+            reid: x.reid,
+            rotations: x.rotations,
+        })
+        .collect();
 
-    if let Some(mut path) = dirs::home_dir() {
-        path.push("EternitySolutions");
-        let _ = fs::create_dir_all(&path);
-        path.push(filename);
-        let _ = fs::write(path, final_output);
+    BoardRecord {
+        depth: score.placed,
+        width: puzzle.width,
+        height: puzzle.height,
+        cells,
+        breaks: score.breaks,
+        matched_edges: score.matched_edges,
+        timestamp: Utc::now().to_rfc3339(),
+        solver_data_seed: solver_data.seed,
+        search_seed,
+        profile: solver_data.config.profile.clone(),
+        worker,
+        url: board_url(puzzle, board),
     }
 }
@@ -0,0 +1,272 @@
+use crate::board_file::{load_boards, parse_board_link, BoardFile};
+use crate::puzzle::Puzzle;
+use crate::structs::BoardScore;
+use crate::util::{board_url, format_board, score_board};
+use log::{error, info, warn};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// What checking a board against a puzzle found: its score, and everything wrong with it.
+#[derive(Debug, Clone, Default)]
+pub struct Verification {
+    pub score: BoardScore,
+    pub problems: Vec<String>,
+}
+
+/// Checks that every piece on the board belongs to the puzzle and is used at most once, that
+/// grey edges sit exactly on the rim, that the clue pieces are where they must be, and that the
+/// link, depth, breaks and matched edges in the file agree with the board. Empty cells are
+/// allowed, so partial boards pass too.
+pub fn verify_board(puzzle: &Puzzle, board_file: &BoardFile) -> Verification {
+    let width = puzzle.width;
+    let height = puzzle.height;
+    let mut problems = Vec::new();
+
+    if board_file.width != width || board_file.height != height {
+        problems.push(format!(
+            "board is {}x{} but the puzzle is {width}x{height}",
+            board_file.width, board_file.height
+        ));
+        return Verification {
+            score: BoardScore::default(),
+            problems,
+        };
+    }
+
+    let board = &board_file.board;
+    let mut used = vec![false; puzzle.pieces.len() + 1];
+    for row in 0..height {
+        for col in 0..width {
+            let rotated_piece = board[row * width + col];
+            if rotated_piece.reid == 0 {
+                continue;
+            }
+            let cell = format!("row {row}, column {col}");
+            let reid = rotated_piece.reid as usize;
+
+            if rotated_piece.rotations > 3 {
+                problems.push(format!(
+                    "{cell}: piece {reid} has rotation {}",
+                    rotated_piece.rotations
+                ));
+                continue;
+            }
+            let Some(piece) = puzzle.placed_piece(&rotated_piece) else {
+                problems.push(format!("{cell}: piece {reid} is not in the piece set"));
+                continue;
+            };
+            if used[reid] {
+                problems.push(format!("{cell}: piece {reid} is used more than once"));
+            }
+            used[reid] = true;
+
+            let sides = [
+                ("top", piece.top, row == height - 1),
+                ("right", piece.right, col == width - 1),
+                ("bottom", piece.bottom, row == 0),
+                ("left", piece.left, col == 0),
+            ];
+            for (name, colour, on_rim) in sides {
+                if on_rim && colour != 0 {
+                    problems.push(format!(
+                        "{cell}: piece {reid} has colour {colour} on the rim at its {name}"
+                    ));
+                } else if !on_rim && colour == 0 {
+                    problems.push(format!(
+                        "{cell}: piece {reid} has a grey edge inside the board at its {name}"
+                    ));
+                }
+            }
+        }
+    }
+
+    if let Some(clue) = puzzle.start_piece {
+        let clue_index = clue.row as usize * width + clue.column as usize;
+        for (index, rotated_piece) in board.iter().enumerate() {
+            let is_clue_piece = rotated_piece.reid == clue.reid;
+            if index == clue_index && rotated_piece.reid > 0 && !is_clue_piece {
+                problems.push(format!(
+                    "row {}, column {}: clue cell holds piece {} instead of piece {}",
+                    clue.row, clue.column, rotated_piece.reid, clue.reid
+                ));
+            } else if index == clue_index
+                && is_clue_piece
+                && rotated_piece.rotations != clue.rotations
+            {
+                problems.push(format!(
+                    "row {}, column {}: clue piece {} has rotation {} instead of {}",
+                    clue.row, clue.column, clue.reid, rotated_piece.rotations, clue.rotations
+                ));
+            } else if index != clue_index && is_clue_piece {
+                problems.push(format!(
+                    "row {}, column {}: clue piece {} must be at row {}, column {}",
+                    index / width,
+                    index % width,
+                    clue.reid,
+                    clue.row,
+                    clue.column
+                ));
+            }
+        }
+    }
+
+    if let Some(url) = &board_file.url {
+        if *url != board_url(puzzle, board) {
+            problems.push("link does not show the same board as the grid".to_string());
+        }
+    }
+
+    let score = score_board(puzzle, board);
+    if let Some(depth) = board_file.depth {
+        if depth != score.placed {
+            problems.push(format!(
+                "file gives depth {depth} but {} pieces are placed",
+                score.placed
+            ));
+        }
+    }
+    if let Some(breaks) = board_file.breaks {
+        if breaks != score.breaks {
+            problems.push(format!(
+                "file gives {breaks} breaks but the board has {}",
+                score.breaks
+            ));
+        }
+    }
+    if let Some(matched_edges) = board_file.matched_edges {
+        if matched_edges != score.matched_edges {
+            problems.push(format!(
+                "file gives {matched_edges} matched edges but the board has {}",
+                score.matched_edges
+            ));
+        }
+    }
+
+    Verification { score, problems }
+}
+
+/// Checks each board file, logging what is wrong with each board. Fails if any of them has a
+/// problem.
+pub fn run_verify(puzzle: &Puzzle, files: &[PathBuf]) -> Result<(), String> {
+    let (mut checked, mut failed) = (0, 0);
+    for path in files {
+        let board_files = match load_boards(path) {
+            Ok(board_files) => board_files,
+            Err(e) => {
+                error!("{}: {e}", path.display());
+                checked += 1;
+                failed += 1;
+                continue;
+            }
+        };
+
+        for (i, board_file) in board_files.iter().enumerate() {
+            // A `boards.jsonl` file holds many boards, which are told apart by their number.
+            let name = match board_files.len() {
+                1 => path.display().to_string(),
+                _ => format!("{} board {}", path.display(), i + 1),
+            };
+            let verification = verify_board(puzzle, board_file);
+            let score = verification.score;
+            checked += 1;
+            if verification.problems.is_empty() {
+                info!(
+                    "{name}: OK, {} pieces, {} breaks, {} matched edges",
+                    score.placed, score.breaks, score.matched_edges
+                );
+            } else {
+                failed += 1;
+                for problem in &verification.problems {
+                    error!("{name}: {problem}");
+                }
+            }
+        }
+    }
+
+    if failed > 0 {
+        return Err(format!("{failed} of {checked} boards failed"));
+    }
+    Ok(())
+}
+
+/// Rebuilds a board from a link, reporting the cells that match no piece, and writes it to
+/// `output`, or to stdout, so that `verify` can check it.
+pub fn run_import(puzzle: &Puzzle, link: &str, output: Option<&Path>) -> Result<(), String> {
+    let linked_board = parse_board_link(puzzle, link)?;
+    let board_file = linked_board.board_file;
+    if board_file.width != puzzle.width || board_file.height != puzzle.height {
+        return Err(format!(
+            "Link shows a {}x{} board but the puzzle is {}x{}",
+            board_file.width, board_file.height, puzzle.width, puzzle.height
+        ));
+    }
+
+    for (row, col) in &linked_board.unidentified {
+        warn!("Row {row}, column {col}: no unused piece matches the edges");
+    }
+    let score = verify_board(puzzle, &board_file).score;
+    info!(
+        "{} pieces, {} breaks, {} matched edges, {} cells unidentified",
+        score.placed,
+        score.breaks,
+        score.matched_edges,
+        linked_board.unidentified.len()
+    );
+
+    let text = format!(
+        "{}\n{}\n",
+        format_board(puzzle, &board_file.board),
+        board_url(puzzle, &board_file.board)
+    );
+    match output {
+        Some(path) => {
+            fs::write(path, text).map_err(|e| format!("Failed to write {}: {e}", path.display()))
+        }
+        None => {
+            print!("{text}");
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board_file::parse_board;
+    use crate::config::SolverConfig;
+    use crate::generate::generate_puzzle;
+    use crate::solver_data::prepare_pieces_and_heuristics;
+    use crate::structs::BoardRecord;
+    use crate::util::board_record;
+
+    #[test]
+    fn recorded_breaks_and_matched_edges_are_checked() {
+        let generated = generate_puzzle(4, 4, 3, 4, 2).unwrap();
+        let puzzle = &generated.puzzle;
+        let solver_data = prepare_pieces_and_heuristics(puzzle, &SolverConfig::for_board(4, 4), 1);
+        // Two middle pieces swapped, so that the board has breaks to count.
+        let mut board = generated.solution.clone();
+        board.swap(5, 10);
+        let record = board_record(&solver_data, &board, 0, 2);
+        assert!(record.breaks > 0);
+        let verify = |record: &BoardRecord| {
+            let board_file = parse_board(&serde_json::to_string(record).unwrap()).unwrap();
+            verify_board(puzzle, &board_file).problems
+        };
+        assert!(verify(&record).is_empty());
+
+        let mut tampered = record.clone();
+        tampered.breaks -= 1;
+        assert_eq!(
+            verify(&tampered),
+            [format!(
+                "file gives {} breaks but the board has {}",
+                record.breaks - 1,
+                record.breaks
+            )]
+        );
+        let mut tampered = record.clone();
+        tampered.matched_edges += 1;
+        assert_eq!(verify(&tampered).len(), 1);
+    }
+}
@@ -0,0 +1,357 @@
+//! Runs the solver binary on a generated puzzle, from the piece file through to the boards it
+//! writes.
+
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::time::{Duration, Instant};
+
+fn run(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_rust"))
+        .args(args)
+        .current_dir(dir)
+        .env("RUST_LOG", "info")
+        .output()
+        .unwrap();
+    // The log goes to standard output.
+    let log = String::from_utf8_lossy(&output.stdout).into_owned();
+    assert!(output.status.success(), "{args:?} failed:\n{log}");
+    log
+}
+
+/// Starts the solver in the background, passing on each line it logs.
+fn spawn(dir: &Path, args: &[&str]) -> (Child, Receiver<String>) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rust"))
+        .args(args)
+        .current_dir(dir)
+        .env("RUST_LOG", "info")
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let (sender, lines) = mpsc::channel();
+    let stdout = BufReader::new(child.stdout.take().unwrap());
+    std::thread::spawn(move || {
+        for line in stdout.lines() {
+            if sender.send(line.unwrap()).is_err() {
+                return;
+            }
+        }
+    });
+    (child, lines)
+}
+
+/// Reads logged lines into `log` until one contains `text`, failing after a minute.
+fn wait_for(lines: &Receiver<String>, log: &mut Vec<String>, text: &str) -> String {
+    let deadline = Instant::now() + Duration::from_secs(60);
+    loop {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        let Ok(line) = lines.recv_timeout(timeout) else {
+            panic!("never logged {text:?}:\n{}", log.join("\n"));
+        };
+        log.push(line.clone());
+        if line.contains(text) {
+            return line;
+        }
+    }
+}
+
+/// Waits for a process to exit, failing after a minute, and returns its remaining log.
+fn wait_to_exit(mut child: Child, lines: &Receiver<String>) -> String {
+    let deadline = Instant::now() + Duration::from_secs(60);
+    let status = loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            break status;
+        }
+        if Instant::now() > deadline {
+            child.kill().unwrap();
+            panic!("still running after a minute");
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    };
+    let log: Vec<_> = lines.iter().collect();
+    assert!(
+        status.success(),
+        "exited with {status}:\n{}",
+        log.join("\n")
+    );
+    log.join("\n")
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("eternity-solver-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn generated_puzzles_are_counted_and_their_solutions_verify() {
+    let dir = temp_dir("cli");
+    run(
+        &dir,
+        &[
+            "generate",
+            "--width=4",
+            "--height=4",
+            "--border-colours=3",
+            "--interior-colours=4",
+            "--seed=2",
+            "--output=pieces.txt",
+            "--solution=solution.txt",
+        ],
+    );
+    let log = run(&dir, &["verify", "--pieces=pieces.txt", "solution.txt"]);
+    assert!(log.contains("solution.txt: OK"), "{log}");
+
+    let log = run(
+        &dir,
+        &[
+            "count",
+            "--pieces=pieces.txt",
+            "--threads=2",
+            "--output-dir=out",
+            "--write",
+        ],
+    );
+    assert!(log.contains("Found 8 solutions"), "{log}");
+    assert!(log.contains("2 distinct solutions"), "{log}");
+
+    let boards: Vec<_> = fs::read_dir(dir.join("out"))
+        .unwrap()
+        .map(|x| x.unwrap().file_name().into_string().unwrap())
+        .filter(|x| x.starts_with("16_"))
+        .collect();
+    assert_eq!(boards.len(), 2);
+    let paths: Vec<_> = boards.iter().map(|x| format!("out/{x}")).collect();
+    let mut args = vec!["verify", "--pieces=pieces.txt"];
+    args.extend(paths.iter().map(String::as_str));
+    let log = run(&dir, &args);
+    assert_eq!(log.matches(": OK, 16 pieces, 0 breaks").count(), 2, "{log}");
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn json_lines_boards_verify_and_render() {
+    let dir = temp_dir("jsonl");
+    run(
+        &dir,
+        &[
+            "generate",
+            "--width=4",
+            "--height=4",
+            "--border-colours=3",
+            "--interior-colours=4",
+            "--seed=2",
+            "--output=pieces.txt",
+        ],
+    );
+    run(
+        &dir,
+        &[
+            "count",
+            "--pieces=pieces.txt",
+            "--output-dir=out",
+            "--output-format=jsonl",
+            "--write",
+        ],
+    );
+
+    let log = run(&dir, &["verify", "--pieces=pieces.txt", "out/boards.jsonl"]);
+    assert_eq!(log.matches(": OK, 16 pieces, 0 breaks").count(), 2, "{log}");
+    assert!(log.contains("out/boards.jsonl board 2: OK"), "{log}");
+
+    run(
+        &dir,
+        &[
+            "render",
+            "--pieces=pieces.txt",
+            "--output=board.svg",
+            "out/boards.jsonl",
+        ],
+    );
+    for name in ["board_1.svg", "board_2.svg"] {
+        assert!(fs::read_to_string(dir.join(name))
+            .unwrap()
+            .starts_with("<svg"));
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn only_runs_that_save_results_record_their_settings() {
+    let dir = temp_dir("profile");
+    run(
+        &dir,
+        &[
+            "generate",
+            "--width=4",
+            "--height=4",
+            "--border-colours=3",
+            "--interior-colours=4",
+            "--seed=2",
+            "--output=pieces.txt",
+        ],
+    );
+    let profiles = || {
+        fs::read_dir(dir.join("out"))
+            .map(|x| {
+                x.map(|x| x.unwrap().file_name().into_string().unwrap())
+                    .filter(|x| x.starts_with("profile_"))
+                    .count()
+            })
+            .unwrap_or(0)
+    };
+
+    run(
+        &dir,
+        &[
+            "bench",
+            "--pieces=pieces.txt",
+            "--output-dir=out",
+            "--max-node-count=1000",
+        ],
+    );
+    run(
+        &dir,
+        &[
+            "estimate",
+            "--pieces=pieces.txt",
+            "--output-dir=out",
+            "--probes=10",
+        ],
+    );
+    assert_eq!(profiles(), 0);
+
+    run(&dir, &["count", "--pieces=pieces.txt", "--output-dir=out"]);
+    assert_eq!(profiles(), 1);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn resumed_exhaustive_runs_count_each_prefix_once() {
+    let dir = temp_dir("exhaust");
+    run(
+        &dir,
+        &[
+            "generate",
+            "--width=4",
+            "--height=4",
+            "--border-colours=3",
+            "--interior-colours=4",
+            "--seed=2",
+            "--output=pieces.txt",
+        ],
+    );
+    let exhaust = |checkpoint: &str, max_node_count: &str| {
+        run(
+            &dir,
+            &[
+                "exhaust",
+                "--pieces=pieces.txt",
+                "--prefix-depth=2",
+                "--threads=1",
+                "--output-dir=out",
+                &format!("--checkpoint={checkpoint}"),
+                &format!("--max-node-count={max_node_count}"),
+            ],
+        );
+        let text = fs::read_to_string(dir.join(checkpoint).join("coverage.json")).unwrap();
+        serde_json::from_str::<serde_json::Value>(&text).unwrap()
+    };
+
+    let cut_off = exhaust("resumed", "20");
+    assert!(!cut_off["cut_off"].as_array().unwrap().is_empty());
+    let resumed = exhaust("resumed", "100000000");
+    let fresh = exhaust("fresh", "100000000");
+    assert!(fresh["cut_off"].as_array().unwrap().is_empty());
+    assert_eq!(resumed["index_counts"], fresh["index_counts"]);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn workers_take_searches_from_the_coordinator_and_send_back_boards() {
+    let dir = temp_dir("listen");
+    run(
+        &dir,
+        &[
+            "generate",
+            "--width=6",
+            "--height=6",
+            "--border-colours=3",
+            "--interior-colours=5",
+            "--seed=3",
+            "--output=pieces.txt",
+        ],
+    );
+    let (coordinator, lines) = spawn(
+        &dir,
+        &[
+            "solve",
+            "--pieces=pieces.txt",
+            "--listen=127.0.0.1:0",
+            "--threads=0",
+            "--slots=2",
+            "--seed=1",
+            "--max-node-count=20000",
+            "--min-solve-index-to-save=12",
+            "--progress-interval=0",
+            "--output-dir=out",
+        ],
+    );
+    let mut log = vec![];
+    let listening = wait_for(&lines, &mut log, "Listening for workers on ");
+    let addr = listening.rsplit(' ').next().unwrap().to_string();
+
+    // A worker asking for more threads than any machine has is turned away.
+    let mut stream = TcpStream::connect(&addr).unwrap();
+    writeln!(
+        stream,
+        r#"{{"type":"hello","name":"huge","threads":100000000}}"#
+    )
+    .unwrap();
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply).unwrap();
+    assert_eq!(reply, "");
+    wait_for(&lines, &mut log, "worker huge asked for 100000000 threads");
+
+    let workers: Vec<_> = ["a", "b"]
+        .iter()
+        .map(|name| {
+            let worker = spawn(
+                &dir,
+                &["work", &addr, "--threads=1", &format!("--name={name}")],
+            );
+            wait_for(&lines, &mut log, &format!("Worker {name} joined"));
+            worker
+        })
+        .collect();
+    // Each loop runs 10 searches, which both workers share.
+    wait_for(&lines, &mut log, "Result after loop 2");
+    let log = log.join("\n");
+    for core in ["Core 00: finish loop", "Core 01: finish loop"] {
+        assert!(log.contains(core), "{log}");
+    }
+    let boards = fs::read_dir(dir.join("out"))
+        .unwrap()
+        .filter(|x| x.as_ref().unwrap().path().extension().unwrap() == "txt")
+        .count();
+    assert!(boards > 0);
+
+    // Stopping the coordinator stops the workers, and all of them exit cleanly.
+    let status = Command::new("kill")
+        .args(["-TERM", &coordinator.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let log = wait_to_exit(coordinator, &lines);
+    assert!(log.contains("Stopped"), "{log}");
+    for (worker, lines) in workers {
+        let log = wait_to_exit(worker, &lines);
+        assert!(log.contains("Coordinator stopped the run"), "{log}");
+        assert!(log.contains("Left the coordinator"), "{log}");
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,412 @@
+//! End-to-end tests of the search on small generated puzzles, through `solve_puzzle` as the
+//! commands run it.
+
+use eternityii_solver::board_file::{load_board, BoardFile};
+use eternityii_solver::board_order::check_board_order;
+use eternityii_solver::config::{OutputFormat, SolverConfig};
+use eternityii_solver::generate::{generate_puzzle, GeneratedPuzzle};
+use eternityii_solver::prefixes::enumerate_prefixes;
+use eternityii_solver::puzzle::Puzzle;
+use eternityii_solver::result_store::ResultStore;
+use eternityii_solver::sinks::{FoundBoard, ResultSink};
+use eternityii_solver::solutions::{canonical_board, num_symmetries, SolutionCollector};
+use eternityii_solver::solve_puzzle::{solve_puzzle, solve_puzzle_counted, SearchState};
+use eternityii_solver::solver_data::{prepare_pieces_and_heuristics, SolverData};
+use eternityii_solver::structs::{Piece, RotatedPiece};
+use eternityii_solver::verify::verify_board;
+use proptest::prelude::*;
+use rand::rngs::StdRng;
+use rand::seq::IndexedRandom;
+use rand::SeedableRng;
+use std::fs;
+use std::io;
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
+
+/// Keeps every board it is sent.
+#[derive(Default)]
+struct Recorder {
+    boards: Mutex<Vec<Vec<RotatedPiece>>>,
+}
+
+impl Recorder {
+    fn boards(&self) -> Vec<Vec<RotatedPiece>> {
+        self.boards.lock().unwrap().clone()
+    }
+}
+
+impl ResultSink for Recorder {
+    fn send(&self, found: &FoundBoard) -> io::Result<()> {
+        self.boards.lock().unwrap().push(found.board.to_vec());
+        Ok(())
+    }
+}
+
+fn generate(width: usize, height: usize, border: u8, interior: u8, seed: u64) -> GeneratedPuzzle {
+    generate_puzzle(width, height, border, interior, seed).unwrap()
+}
+
+/// Solver data that sends every new deepest board, and every full one, to the sink.
+fn solver_data(puzzle: &Puzzle, config: Option<SolverConfig>, seed: u64) -> SolverData {
+    let config = SolverConfig {
+        min_solve_index_to_save: 1,
+        ..config.unwrap_or_else(|| SolverConfig::for_board(puzzle.width, puzzle.height))
+    };
+    prepare_pieces_and_heuristics(puzzle, &config, seed)
+}
+
+/// Searches below every prefix `prefix_depth` pieces long, as `exhaust` and `count` do, and
+/// returns whether every one was searched to the end.
+fn search_everything(
+    solver_data: &SolverData,
+    prefix_depth: usize,
+    max_node_count: u64,
+    sink: &dyn ResultSink,
+) -> bool {
+    enumerate_prefixes(solver_data, prefix_depth)
+        .iter()
+        .map(|prefix| {
+            let state = SearchState::from_prefix(solver_data, 0, prefix);
+            solve_puzzle(
+                solver_data,
+                state,
+                max_node_count,
+                Some(sink),
+                None,
+                None,
+                None,
+            )
+        })
+        .all(|x| x.exhausted)
+}
+
+/// The pieces and rotations of a board, which is all that tells two boards apart.
+fn key(board: &[RotatedPiece]) -> Vec<(u16, u8)> {
+    board.iter().map(|x| (x.reid, x.rotations)).collect()
+}
+
+/// The planted solution as `count` lists it.
+fn planted(generated: &GeneratedPuzzle) -> Vec<(u16, u8)> {
+    key(&canonical_board(&generated.puzzle, &generated.solution))
+}
+
+/// Counts the solutions of a puzzle, returning how many full boards were found and the distinct
+/// ones.
+fn count(solver_data: &SolverData, prefix_depth: usize) -> (u64, Vec<Vec<RotatedPiece>>) {
+    let distinct = Arc::new(Recorder::default());
+    let collector = SolutionCollector::new(&solver_data.puzzle, Some(Box::new(distinct.clone())));
+    assert!(search_everything(
+        solver_data,
+        prefix_depth,
+        u64::MAX / 2,
+        &collector
+    ));
+    (collector.found(), distinct.boards())
+}
+
+/// Checks a board from the search: the cells filled are the first in the search order, every
+/// piece is on the board at most once with its grey edges on the rim, and no more edges are
+/// broken than the breaks allowed by its depth.
+fn check_board(solver_data: &SolverData, board: &[RotatedPiece]) -> Result<(), String> {
+    let puzzle = &solver_data.puzzle;
+    let depth = board.iter().filter(|x| x.reid > 0).count();
+    for (position, cell) in solver_data.board_search_sequence.iter().enumerate() {
+        let filled = board[cell.row as usize * puzzle.width + cell.column as usize].reid > 0;
+        if filled != (position < depth) {
+            return Err(format!(
+                "position {position} is filled out of order on a board {depth} deep"
+            ));
+        }
+    }
+    let board_file = BoardFile {
+        width: puzzle.width,
+        height: puzzle.height,
+        board: board.to_vec(),
+        depth: None,
+        breaks: None,
+        matched_edges: None,
+        url: None,
+        solver_data_seed: None,
+        search_seed: None,
+        profile: None,
+    };
+    let verification = verify_board(puzzle, &board_file);
+    if !verification.problems.is_empty() {
+        return Err(verification.problems.join(", "));
+    }
+    let allowed = match depth {
+        0 => 0,
+        _ => solver_data.break_array[depth - 1] as usize,
+    };
+    if verification.score.breaks > allowed {
+        return Err(format!(
+            "{} breaks on a board {depth} deep, where {allowed} are allowed",
+            verification.score.breaks
+        ));
+    }
+    Ok(())
+}
+
+/// A search order that fills each cell after the ones below it and to its left, chosen at
+/// random among those still free, in the form `SolverConfig::board_order` takes.
+fn random_board_order(width: usize, height: usize, seed: u64) -> Vec<Vec<usize>> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut order = vec![vec![usize::MAX; width]; height];
+    for position in 0..width * height {
+        let free: Vec<_> = (0..height)
+            .flat_map(|row| (0..width).map(move |col| (row, col)))
+            .filter(|&(row, col)| {
+                let filled = |row: usize, col: usize| order[height - 1 - row][col] != usize::MAX;
+                !filled(row, col)
+                    && (row == 0 || filled(row - 1, col))
+                    && (col == 0 || filled(row, col - 1))
+            })
+            .collect();
+        let &(row, col) = free.choose(&mut rng).unwrap();
+        order[height - 1 - row][col] = position;
+    }
+    order
+}
+
+#[test]
+fn counts_match_the_symmetries_of_each_solution() {
+    for (width, height, border, interior, seed) in [
+        (3, 3, 2, 3, 1),
+        (4, 4, 3, 4, 2),
+        (5, 3, 2, 4, 3),
+        (5, 5, 4, 8, 4),
+    ] {
+        let generated = generate(width, height, border, interior, seed);
+        let solver_data = solver_data(&generated.puzzle, None, seed);
+        let (found, distinct) = count(&solver_data, 1);
+        // No solution of a generated puzzle is the same board turned round, since every piece
+        // is different.
+        assert_eq!(
+            found,
+            distinct.len() as u64 * num_symmetries(&generated.puzzle) as u64
+        );
+        assert!(distinct.iter().any(|x| key(x) == planted(&generated)));
+        for board in &distinct {
+            assert_eq!(key(board), key(&canonical_board(&generated.puzzle, board)));
+            check_board(&solver_data, board).unwrap();
+        }
+    }
+}
+
+#[test]
+fn prefix_depth_does_not_change_the_count() {
+    let generated = generate(5, 4, 3, 5, 5);
+    let solver_data = solver_data(&generated.puzzle, None, 5);
+    let (found, distinct) = count(&solver_data, 1);
+    for prefix_depth in [2, 3, 6] {
+        let (deeper_found, deeper_distinct) = count(&solver_data, prefix_depth);
+        assert_eq!(deeper_found, found);
+        assert_eq!(deeper_distinct.len(), distinct.len());
+    }
+}
+
+#[test]
+fn prefixes_as_deep_as_the_board_count_each_solution_once() {
+    let generated = generate(4, 4, 3, 4, 2);
+    let solver_data = solver_data(&generated.puzzle, None, 2);
+    let num_cells = generated.puzzle.num_cells();
+    let (found, distinct) = count(&solver_data, 1);
+    for prefix_depth in [num_cells - 1, num_cells, num_cells + 24] {
+        let (deeper_found, deeper_distinct) = count(&solver_data, prefix_depth);
+        assert_eq!(deeper_found, found);
+        assert_eq!(deeper_distinct.len(), distinct.len());
+    }
+}
+
+#[test]
+fn random_searches_solve_small_puzzles() {
+    // On a square board every corner starts a solution turned one way or another.
+    let generated = generate(5, 5, 4, 10, 6);
+    for seed in 0..8 {
+        let solver_data = solver_data(&generated.puzzle, None, seed);
+        let state = SearchState::new(&solver_data, 0, seed);
+        let result = solve_puzzle(&solver_data, state, u64::MAX / 2, None, None, None, None);
+        assert_eq!(result.max_depth, generated.puzzle.num_cells());
+        assert_eq!(result.best_score.breaks, 0);
+        assert!(!result.exhausted && !result.stopped);
+        check_board(&solver_data, &result.best_board).unwrap();
+    }
+}
+
+#[test]
+fn searches_repeat_and_resume_exactly() {
+    let generated = generate(6, 6, 4, 6, 7);
+    let solver_data = solver_data(&generated.puzzle, None, 7);
+    let run = |state: SearchState| {
+        let recorder = Recorder::default();
+        let result = solve_puzzle(
+            &solver_data,
+            state,
+            200_000,
+            Some(&recorder),
+            None,
+            None,
+            None,
+        );
+        (result.solve_indexes, result.best_board, recorder.boards())
+    };
+    let first = run(SearchState::new(&solver_data, 0, 7));
+    assert_eq!(run(SearchState::new(&solver_data, 0, 7)), first);
+
+    // A search stopped at once hands over its starting state, which then runs as before.
+    let stop = AtomicBool::new(true);
+    let saved = Mutex::new(None);
+    let checkpoint = |state: SearchState| *saved.lock().unwrap() = Some(state);
+    let stopped = solve_puzzle(
+        &solver_data,
+        SearchState::new(&solver_data, 0, 7),
+        200_000,
+        None,
+        None,
+        Some(&stop),
+        Some(&checkpoint),
+    );
+    assert!(stopped.stopped);
+    assert_eq!(run(saved.into_inner().unwrap().unwrap()), first);
+}
+
+#[test]
+fn long_candidate_lists_are_searched_once_each() {
+    // A 258x3 board whose 256 middles all fit the first middle cell and nothing after it.
+    let piece = |top, right, bottom, left| Piece {
+        reid: 0,
+        top,
+        right,
+        bottom,
+        left,
+    };
+    let mut pieces = vec![piece(0, 0, 5, 5); 4];
+    pieces.extend(vec![piece(5, 5, 0, 5); 514]);
+    pieces.extend(vec![piece(3, 4, 1, 2); 256]);
+    for (i, x) in pieces.iter_mut().enumerate() {
+        x.reid = i as u16 + 1;
+    }
+    let puzzle = Puzzle::from_pieces(pieces).unwrap();
+    let solver_data = solver_data(&puzzle, None, 0);
+    // The bottom row, with tops of colour 1, and the left side, with a right of colour 2.
+    let prefix: Vec<_> = (1..=puzzle.width as u16 + 1)
+        .map(|reid| RotatedPiece {
+            reid,
+            top: 1,
+            right: if reid as usize > puzzle.width { 2 } else { 5 },
+            ..RotatedPiece::default()
+        })
+        .collect();
+    let state = SearchState::from_prefix(&solver_data, 0, &prefix);
+    assert_eq!(state.candidates(&solver_data).len(), 256);
+
+    let result = solve_puzzle(&solver_data, state, 1_000_000, None, None, None, None);
+    assert!(result.exhausted);
+    assert_eq!(result.solve_indexes[prefix.len() + 1], 256);
+}
+
+#[test]
+fn counting_does_not_change_the_search() {
+    let generated = generate(6, 6, 4, 6, 9);
+    let solver_data = solver_data(&generated.puzzle, None, 9);
+    let state = SearchState::new(&solver_data, 0, 9);
+    let plain = solve_puzzle(&solver_data, state.clone(), 200_000, None, None, None, None);
+    let counted = solve_puzzle_counted(&solver_data, state, 200_000, None, None, None, None);
+    assert_eq!(counted.solve_indexes, plain.solve_indexes);
+    assert_eq!(counted.best_board, plain.best_board);
+    assert_eq!(counted.nodes, plain.nodes);
+    assert_eq!((plain.candidates_scanned, plain.backtracks), (0, 0));
+    // Every step forward scans at least the candidate it places.
+    assert!(counted.backtracks > 0 && counted.backtracks < counted.nodes);
+    assert!(counted.candidates_scanned >= counted.nodes - counted.backtracks - 1);
+}
+
+#[test]
+fn saved_boards_read_back_and_verify() {
+    let dir = std::env::temp_dir().join(format!("eternity-solver-test-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let generated = generate(4, 4, 3, 5, 8);
+    let solver_data = solver_data(&generated.puzzle, None, 8);
+    let store = ResultStore::open(&dir, OutputFormat::Text).unwrap();
+    let state = SearchState::new(&solver_data, 3, 8);
+    let result = solve_puzzle(
+        &solver_data,
+        state,
+        u64::MAX / 2,
+        Some(&store),
+        None,
+        None,
+        None,
+    );
+    assert_eq!(result.max_depth, generated.puzzle.num_cells());
+
+    // Every new deepest board is saved, from two pieces on.
+    let index = store.index();
+    assert_eq!(index.boards.len(), generated.puzzle.num_cells() - 1);
+    for entry in index.boards.values() {
+        let board_file = load_board(&dir.join(entry.file.as_ref().unwrap())).unwrap();
+        assert_eq!(board_file.solver_data_seed, Some(8));
+        assert_eq!(board_file.search_seed, Some(8));
+        assert!(verify_board(&generated.puzzle, &board_file)
+            .problems
+            .is_empty());
+        check_board(&solver_data, &board_file.board).unwrap();
+    }
+    // Finding a board again is recorded, but it is not written out twice.
+    assert!(!store.save(&solver_data, &result.best_board, 3, 8).unwrap());
+    assert_eq!(store.index().boards.len(), generated.puzzle.num_cells() - 1);
+    drop(store);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(48))]
+
+    #[test]
+    fn every_board_the_search_sends_is_valid(
+        width in 3usize..=6,
+        height in 3usize..=5,
+        border in 1u8..=4,
+        interior in 1u8..=8,
+        seed: u64,
+        break_positions in prop::collection::vec(1usize..30, 0..3),
+    ) {
+        let generated = generate(width, height, border, interior, seed);
+        let puzzle = &generated.puzzle;
+        let board_order = random_board_order(puzzle.width, puzzle.height, seed);
+        prop_assert_eq!(check_board_order(&board_order, puzzle.width, puzzle.height), Ok(()));
+        let config = SolverConfig {
+            board_order,
+            break_indexes_allowed: break_positions,
+            ..SolverConfig::for_board(puzzle.width, puzzle.height)
+        };
+        let solver_data = solver_data(puzzle, Some(config), seed);
+
+        let recorder = Recorder::default();
+        search_everything(&solver_data, 1, 10_000, &recorder);
+        let state = SearchState::new(&solver_data, 0, seed);
+        let result = solve_puzzle(&solver_data, state, 10_000, Some(&recorder), None, None, None);
+        check_board(&solver_data, &result.best_board).map_err(TestCaseError::fail)?;
+        for board in recorder.boards() {
+            check_board(&solver_data, &board).map_err(TestCaseError::fail)?;
+        }
+    }
+
+    #[test]
+    fn the_planted_solution_is_always_found(
+        width in 3usize..=4,
+        height in 3usize..=4,
+        border in 2u8..=4,
+        interior in 4u8..=8,
+        seed: u64,
+    ) {
+        let generated = generate(width, height, border, interior, seed);
+        let solver_data = solver_data(&generated.puzzle, None, seed);
+        let (found, distinct) = count(&solver_data, 1);
+        prop_assert!(distinct.iter().any(|x| key(x) == planted(&generated)));
+        prop_assert_eq!(
+            found,
+            distinct.len() as u64 * num_symmetries(&generated.puzzle) as u64
+        );
+    }
+}